@@ -0,0 +1,155 @@
+//! Compares the old glob-based /dev scan against the single read_dir pass that
+//! get_path_for_dev now uses, on a synthetic /dev with many entries, and measures
+//! get_path_for_dev_in itself against a /dev full of real device nodes. The last
+//! group resolves every terminal in /dev: one lookup per device stats each node
+//! once per lookup, while list_terminals shares a single scan between all of them.
+
+#[cfg(target_os = "linux")]
+#[macro_use]
+extern crate criterion;
+#[cfg(target_os = "linux")]
+extern crate ctty;
+#[cfg(target_os = "linux")]
+extern crate glob;
+#[cfg(target_os = "linux")]
+extern crate libc;
+
+#[cfg(target_os = "linux")]
+mod bench {
+    use std::ffi::CString;
+    use std::fs;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+
+    use criterion::{BenchmarkId, Criterion};
+
+    use ctty;
+    use glob::glob;
+
+    /// A dev that will never match, so every scan covers the whole directory
+    const MISSING_DEV: u64 = !0;
+
+    /// Creates a directory holding n tty* entries and n unrelated ones, like /dev
+    fn make_fake_dev(n: usize) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ctty-bench-dev-{}-{}", std::process::id(), n));
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..n {
+            fs::write(dir.join(format!("tty{}", i)), b"").unwrap();
+            fs::write(dir.join(format!("sda{}", i)), b"").unwrap();
+        }
+        dir
+    }
+
+    /// Creates a directory holding n tty* character devices and n unrelated files, or
+    /// None if device nodes can't be created (mknod needs CAP_MKNOD)
+    fn make_fake_dev_nodes(n: usize) -> Option<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("ctty-bench-nodes-{}-{}", std::process::id(), n));
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..n {
+            let path = CString::new(dir.join(format!("tty{}", i)).as_os_str().as_bytes()).unwrap();
+            let dev = libc::makedev(4, i as u32);
+            if unsafe { libc::mknod(path.as_ptr(), libc::S_IFCHR | 0o600, dev) } != 0 {
+                fs::remove_dir_all(&dir).unwrap();
+                return None;
+            }
+            fs::write(dir.join(format!("sda{}", i)), b"").unwrap();
+        }
+        Some(dir)
+    }
+
+    /// Returns the dev of the tty* entry a scan of dir reaches last, the worst case
+    /// for a lookup that succeeds
+    fn last_scanned_dev(dir: &Path) -> ctty::Dev {
+        let last = fs::read_dir(dir).unwrap().filter_map(Result::ok)
+            .filter(|entry| entry.file_name().as_bytes().starts_with(b"tty"))
+            .last().unwrap();
+        ctty::Dev(fs::symlink_metadata(last.path()).unwrap().rdev())
+    }
+
+    /// The previous implementation: glob for tty* and stat every match
+    fn scan_glob(dev: u64, dir: &Path) -> Option<PathBuf> {
+        let pattern = format!("{}/tty*", dir.display());
+        for path in glob(&pattern).unwrap().filter_map(Result::ok) {
+            match fs::metadata(&path) {
+                Ok(m) if m.rdev() == dev => return Some(path),
+                _ => continue
+            }
+        }
+        None
+    }
+
+    /// The current implementation: one read_dir pass, lstat-ing only the tty* entries
+    fn scan_read_dir(dev: u64, dir: &Path) -> Option<PathBuf> {
+        for entry in fs::read_dir(dir).unwrap().filter_map(Result::ok) {
+            if !entry.file_name().as_bytes().starts_with(b"tty") {
+                continue;
+            }
+            match fs::symlink_metadata(entry.path()) {
+                Ok(m) if m.rdev() == dev => return Some(entry.path()),
+                _ => continue
+            }
+        }
+        None
+    }
+
+    pub fn bench_scan(c: &mut Criterion) {
+        let mut group = c.benchmark_group("dev_scan");
+        for &n in &[100, 1000] {
+            let dir = make_fake_dev(n);
+            group.bench_with_input(BenchmarkId::new("glob", n), &dir, |b, dir| {
+                b.iter(|| scan_glob(MISSING_DEV, dir))
+            });
+            group.bench_with_input(BenchmarkId::new("read_dir", n), &dir, |b, dir| {
+                b.iter(|| scan_read_dir(MISSING_DEV, dir))
+            });
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        group.finish();
+
+        let mut group = c.benchmark_group("get_path_for_dev_in");
+        for &n in &[100, 1000] {
+            let dir = match make_fake_dev_nodes(n) {
+                Some(dir) => dir,
+                None => break
+            };
+            let last = last_scanned_dev(&dir);
+            group.bench_with_input(BenchmarkId::new("last", n), &dir, |b, dir| {
+                b.iter(|| ctty::get_path_for_dev_in(last, dir))
+            });
+            group.bench_with_input(BenchmarkId::new("missing", n), &dir, |b, dir| {
+                b.iter(|| ctty::get_path_for_dev_in(ctty::Dev(MISSING_DEV), dir))
+            });
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        group.finish();
+
+        // The real thing, when there's a ctty to look for
+        if let Ok(dev) = ctty::get_ctty_dev() {
+            c.bench_function("get_path_for_dev", |b| b.iter(|| ctty::get_path_for_dev(dev)));
+        }
+
+        let devs: Vec<ctty::Dev> = ctty::list_terminals().unwrap().into_iter()
+            .map(|(_, dev)| dev)
+            .collect();
+        let mut group = c.benchmark_group("resolve_all_terminals");
+        group.bench_function("per_lookup", |b| b.iter(|| {
+            devs.iter().map(|&dev| ctty::get_path_for_dev_in(dev, Path::new("/dev")).ok())
+                .collect::<Vec<_>>()
+        }));
+        group.bench_function("one_scan", |b| b.iter(|| {
+            let terminals = ctty::list_terminals().unwrap();
+            devs.iter().map(|&dev| terminals.iter().find(|(_, d)| *d == dev).map(|(path, _)| path.clone()))
+                .collect::<Vec<_>>()
+        }));
+        group.finish();
+    }
+}
+
+#[cfg(target_os = "linux")]
+criterion_group!(benches, bench::bench_scan);
+#[cfg(target_os = "linux")]
+criterion_main!(benches);
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
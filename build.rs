@@ -1,10 +1,34 @@
-#[cfg(any(target_os = "freebsd", target_os = "macos"))]
-extern crate cc;
+#[cfg(feature = "bindgen")]
+extern crate bindgen;
 
 fn main() {
-    // On FreeBSD and macOS, build the sysctl wrapper
-    #[cfg(any(target_os = "freebsd", target_os = "macos"))]
-    cc::Build::new()
-        .file("src/bsd.c")
-        .compile("bsdwrapper");
+    // On FreeBSD and macOS, optionally generate kinfo_proc instead of using the
+    // built-in definitions
+    #[cfg(feature = "bindgen")]
+    generate_kinfo_proc();
+}
+
+#[cfg(feature = "bindgen")]
+fn generate_kinfo_proc() {
+    use std::env;
+    use std::path::PathBuf;
+
+    // build.rs runs on the host, so the target has to be checked at runtime
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    if target_os != "freebsd" && target_os != "macos" {
+        return;
+    }
+
+    let bindings = bindgen::Builder::default()
+        .header_contents("kinfo_proc.h", "#include <sys/types.h>\n\
+                                          #include <sys/sysctl.h>\n\
+                                          #include <sys/user.h>\n")
+        .allowlist_type("kinfo_proc")
+        .layout_tests(false)
+        .generate()
+        .expect("Failed to generate kinfo_proc bindings");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    bindings.write_to_file(out_dir.join("kinfo_proc.rs"))
+        .expect("Failed to write kinfo_proc bindings");
 }
@@ -0,0 +1,41 @@
+//! Prints the controlling tty like tty(1) does, along with its device number and
+//! what kind of terminal it is. Exits with status 1 if there's no controlling tty.
+//!
+//!     $ cargo run --example ctty
+//!     /dev/pts/3
+//!     device: 136:3 (raw 34819)
+//!     kind:   pseudo tty 3
+
+extern crate ctty;
+
+use std::process;
+
+use ctty::{CttyError, CttyKind};
+
+fn describe(kind: CttyKind) -> String {
+    match kind {
+        CttyKind::VirtualConsole(n) => format!("virtual console {}", n),
+        CttyKind::SerialConsole => "serial line".to_string(),
+        CttyKind::Pseudo(n) => format!("pseudo tty {}", n),
+        CttyKind::Other => "other".to_string()
+    }
+}
+
+fn main() {
+    let ctty = match ctty::Ctty::for_current_process() {
+        Ok(ctty) => ctty,
+        Err(CttyError::NotFound) => {
+            eprintln!("ctty: not a tty (this process has no controlling terminal)");
+            process::exit(1);
+        },
+        Err(e) => {
+            eprintln!("ctty: {}", e);
+            process::exit(1);
+        }
+    };
+
+    println!("{}", ctty.path.display());
+    // Dev displays as major:minor wherever there's such a split
+    println!("device: {} (raw {})", ctty.dev, ctty.dev.0);
+    println!("kind:   {}", describe(ctty::classify_dev(ctty.dev)));
+}
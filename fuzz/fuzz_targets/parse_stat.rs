@@ -0,0 +1,21 @@
+// Feeds arbitrary bytes to the /proc/<pid>/stat parser. Run it from the repository
+// root with cargo-fuzz (which needs a nightly toolchain):
+//
+//     cargo install cargo-fuzz
+//     cargo +nightly fuzz run parse_stat
+//
+// Inputs that crash it are saved under fuzz/artifacts/parse_stat, and can be replayed
+// with `cargo +nightly fuzz run parse_stat <file>`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The stat parser must reject malformed input with an error, never panic
+fuzz_target!(|data: &[u8]| {
+    if let Ok(stat) = std::str::from_utf8(data) {
+        let _ = ctty::parse_ctty_dev_from_stat(stat);
+        let _ = ctty::parse_proc_stat(stat);
+    }
+    let _ = ctty::get_ctty_dev_from_reader(data);
+});
@@ -1,5 +1,8 @@
 //! ctty-rs is a cross-platform crate for determining a processes' controlling TTY (ctty).
-//! Support is currently available for Linux, macOS, and FreeBSD.
+//! Support is currently available for Linux, Android, macOS, FreeBSD, DragonFly BSD,
+//! NetBSD, OpenBSD, illumos, Solaris, AIX, Haiku, GNU/Hurd, and Redox. On any other
+//! platform, including Windows, the crate still builds, but the lookup functions
+//! always fail with `CttyError::Unsupported`.
 //!
 //! In many cases, it may be useful to know which TTY a process belongs to
 //! (for example, when storing session data), but there is no standardized way to 
@@ -8,163 +11,5077 @@
 //!
 //! ctty-rs provides a simple way to obtain a processes' controlling TTY even when
 //! stdin, stdout, and stderr with a platform-agnostic interface.
+//!
+//! The easiest way to get started is with `Ctty::for_current_process`, which looks up
+//! both the device number and path of the controlling TTY:
+//!
+//! ```no_run
+//! let ctty = ctty::Ctty::for_current_process().unwrap();
+//! println!("{} ({})", ctty.path.display(), ctty.dev);
+//! ```
+//!
+//! The underlying free functions (`get_ctty_dev`, `get_path_for_dev`, etc.) remain
+//! available for lower-level use. They pass device numbers around as `Dev`, which
+//! converts to and from the raw `u64` dev_t and displays as major:minor. The most
+//! common ones, along with `Ctty`, `CttyError`, and `CttyKind`, can be imported at
+//! once with `use ctty::prelude::*`.
+//!
+//! # no_std support
+//!
+//! The `std` feature is enabled by default. When it is disabled, the crate builds as
+//! `#![no_std]` (it still requires `alloc`) and only the Linux `/proc` lookup is provided:
+//! `get_ctty_dev`, `get_ctty_dev_for_pid`, `get_ctty_dev_thread`, `get_parent_ctty_dev`,
+//! `get_raw_tty_nr`, `has_ctty`, `major`, and `minor`. These read `/proc` with raw
+//! syscalls, and IO failures are reported as `CttyError::IOError` holding the raw
+//! errno. Path resolution (`get_path_for_dev` and friends) and `Ctty` require `std`,
+//! as do all other platforms.
+//!
+//! # Static linking
+//!
+//! On Linux, the only dependencies beyond std are `libc` and `thiserror` (neither of
+//! which pulls in anything else), and `/dev` is scanned with std's own metadata calls,
+//! so fully static musl builds stay small. With `std` disabled, only `libc` is used.
+//!
+//! `thiserror` is a default feature. Building with `default-features = false,
+//! features = ["std"]` drops it, and `CttyError` implements `Display` and `Error` by
+//! hand instead, with the same messages.
+//!
+//! # Async support
+//!
+//! On Linux and Android, the `tokio` feature adds `get_ctty_dev_async` and
+//! `get_path_for_dev_async`, which read `/proc` with `tokio::fs` and scan `/dev` on
+//! tokio's blocking thread pool so they don't stall the executor.
+//!
+//! # Login records
+//!
+//! The `utmp` feature adds `get_utmp_entry`, which finds who is logged in on the
+//! controlling TTY (and from where, and since when) in the utmp database. It's
+//! available on glibc Linux, macOS, FreeBSD, DragonFly BSD, NetBSD, illumos, and
+//! Solaris.
+//!
+//! # libkvm
+//!
+//! On FreeBSD, the `kvm` feature links libkvm and reads the ctty with `kvm_getprocs`
+//! first, falling back to the `KERN_PROC` sysctl if that fails. This is for hardened
+//! systems where only one of the two is allowed.
+//!
+//! # Logging
+//!
+//! The `tracing` feature emits `tracing` events as the controlling TTY is looked up:
+//! which proc file was read, the decoded tty_nr, each `/dev` entry checked (inside a
+//! `scan_dev` span), and which match was found. Without the feature, none of this is
+//! compiled in.
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// Only the Linux /proc lookup formats strings, so elsewhere alloc's macros go unused
+#[cfg(not(feature = "std"))]
+#[cfg_attr(any(target_os = "linux", target_os = "android"), macro_use)]
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fmt;
+
+#[cfg(feature = "std")]
+use std::ffi::OsString;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, OnceLock};
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+#[cfg(feature = "thiserror")]
 extern crate thiserror;
+#[cfg(feature = "thiserror")]
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+// Forward to tracing when the feature is enabled, and compile to nothing otherwise.
+// Unsupported platforms have nothing to log, so these can go unused
+#[cfg(feature = "tracing")]
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)*) => { ::tracing::trace!($($arg)*) }
+}
+#[cfg(not(feature = "tracing"))]
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)*) => {}
+}
+#[cfg(feature = "tracing")]
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($($arg:tt)*) => { ::tracing::debug!($($arg)*) }
+}
+#[cfg(not(feature = "tracing"))]
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($($arg:tt)*) => {}
+}
+
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "thiserror", derive(Error))]
+#[derive(Debug)]
 pub enum CttyError {
-    #[error("Controlling TTY for this process not found")]
+    /// No controlling tty, or no device node for it, could be found
+    ///
+    /// Under WSL the kernel reports tty numbers that often have no counterpart in
+    /// /dev. Path lookups for the current process's ctty fall back to ttyname on
+    /// stdin, stdout, and stderr there, so they can still fail with this if all three
+    /// are redirected.
+    #[cfg_attr(feature = "thiserror", error("Controlling TTY for this process not found"))]
     NotFound,
 
-    #[error("System returned invalid data when looking up CTTY")]
+    /// The process has a controlling tty, but no device node for it was found where
+    /// /dev was searched
+    ///
+    /// This happens in containers and other mount namespaces whose /dev doesn't
+    /// include the host's terminal devices, e.g. when a PID namespace shares the
+    /// host's tty but not its devpts mount.
+    #[cfg_attr(feature = "thiserror",
+               error("Controlling TTY exists but has no device node in the searched /dev"))]
+    DeviceNotInDev,
+
+    /// A pseudo tty was looked up, but the searched /dev has no devpts mounted, so
+    /// there was nowhere it could have been found
+    ///
+    /// Minimal systems and containers don't always mount devpts; programs can still
+    /// reach their ctty through /dev/tty there.
+    #[cfg_attr(feature = "thiserror", error("Pseudo tty requested, but devpts is not mounted at the searched /dev/pts"))]
+    DevptsNotMounted,
+
+    #[cfg_attr(feature = "thiserror", error("System returned invalid data when looking up CTTY"))]
+    SystemDataParseFailure,
+
+    /// The system refused the request, with the contained errno (EACCES or EPERM)
+    #[cfg_attr(feature = "thiserror",
+               error("Failed to request CTTY information from system: {}",
+                     std::io::Error::from_raw_os_error(*.0)))]
+    SystemPermissionFailure(i32),
+
+    /// ctty-rs doesn't support the current platform, or it has no notion of a
+    /// controlling tty at all (e.g. Windows)
+    #[cfg_attr(feature = "thiserror", error("Controlling TTYs are not supported on this platform"))]
+    Unsupported,
+
+    /// detach_ctty was called by a process group leader (such as one that already
+    /// leads its own session), which setsid refuses
+    #[cfg_attr(feature = "thiserror",
+               error("Process is already a session leader, so it can't detach from its CTTY"))]
+    AlreadySessionLeader,
+
+    #[cfg_attr(feature = "thiserror", error(transparent))]
+    IOError(#[cfg_attr(feature = "thiserror", from)] std::io::Error)
+}
+
+// Without thiserror, implement by hand what its derive generates, with the same messages
+#[cfg(all(feature = "std", not(feature = "thiserror")))]
+impl fmt::Display for CttyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CttyError::NotFound => write!(f, "Controlling TTY for this process not found"),
+            CttyError::DeviceNotInDev => {
+                write!(f, "Controlling TTY exists but has no device node in the searched /dev")
+            },
+            CttyError::DevptsNotMounted => {
+                write!(f, "Pseudo tty requested, but devpts is not mounted at the searched /dev/pts")
+            },
+            CttyError::SystemDataParseFailure => {
+                write!(f, "System returned invalid data when looking up CTTY")
+            },
+            CttyError::SystemPermissionFailure(errno) => {
+                write!(f, "Failed to request CTTY information from system: {}",
+                       std::io::Error::from_raw_os_error(errno))
+            },
+            CttyError::Unsupported => write!(f, "Controlling TTYs are not supported on this platform"),
+            CttyError::AlreadySessionLeader => {
+                write!(f, "Process is already a session leader, so it can't detach from its CTTY")
+            },
+            CttyError::IOError(ref e) => fmt::Display::fmt(e, f)
+        }
+    }
+}
+
+#[cfg(all(feature = "std", not(feature = "thiserror")))]
+impl std::error::Error for CttyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            // Transparent, like thiserror's #[error(transparent)]
+            CttyError::IOError(ref e) => e.source(),
+            _ => None
+        }
+    }
+}
+
+#[cfg(all(feature = "std", not(feature = "thiserror")))]
+impl From<std::io::Error> for CttyError {
+    fn from(e: std::io::Error) -> CttyError {
+        CttyError::IOError(e)
+    }
+}
+
+/// IO errors compare equal when their kinds match, since std::io::Error itself can't
+/// be compared
+#[cfg(feature = "std")]
+impl PartialEq for CttyError {
+    fn eq(&self, other: &CttyError) -> bool {
+        match (self, other) {
+            (CttyError::NotFound, CttyError::NotFound) => true,
+            (CttyError::DeviceNotInDev, CttyError::DeviceNotInDev) => true,
+            (CttyError::DevptsNotMounted, CttyError::DevptsNotMounted) => true,
+            (CttyError::SystemDataParseFailure, CttyError::SystemDataParseFailure) => true,
+            (CttyError::SystemPermissionFailure(a), CttyError::SystemPermissionFailure(b)) => a == b,
+            (CttyError::Unsupported, CttyError::Unsupported) => true,
+            (CttyError::AlreadySessionLeader, CttyError::AlreadySessionLeader) => true,
+            (CttyError::IOError(a), CttyError::IOError(b)) => a.kind() == b.kind(),
+            _ => false
+        }
+    }
+}
+
+/// std::io::Error can't be cloned, so a clone of an IO error is rebuilt from its errno,
+/// or failing that from its kind and message. Either way it displays the same, but
+/// anything wrapped as its source is lost.
+#[cfg(feature = "std")]
+impl Clone for CttyError {
+    fn clone(&self) -> CttyError {
+        match *self {
+            CttyError::NotFound => CttyError::NotFound,
+            CttyError::DeviceNotInDev => CttyError::DeviceNotInDev,
+            CttyError::DevptsNotMounted => CttyError::DevptsNotMounted,
+            CttyError::SystemDataParseFailure => CttyError::SystemDataParseFailure,
+            CttyError::SystemPermissionFailure(errno) => CttyError::SystemPermissionFailure(errno),
+            CttyError::Unsupported => CttyError::Unsupported,
+            CttyError::AlreadySessionLeader => CttyError::AlreadySessionLeader,
+            CttyError::IOError(ref e) => CttyError::IOError(match e.raw_os_error() {
+                Some(errno) => std::io::Error::from_raw_os_error(errno),
+                None => std::io::Error::new(e.kind(), e.to_string())
+            })
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<CttyError> for std::io::Error {
+    fn from(e: CttyError) -> std::io::Error {
+        use std::io::{Error, ErrorKind};
+
+        let kind = match e {
+            CttyError::NotFound => ErrorKind::NotFound,
+            CttyError::DeviceNotInDev => ErrorKind::NotFound,
+            CttyError::DevptsNotMounted => ErrorKind::NotFound,
+            CttyError::SystemDataParseFailure => ErrorKind::InvalidData,
+            CttyError::SystemPermissionFailure(_) => ErrorKind::PermissionDenied,
+            CttyError::Unsupported => ErrorKind::Unsupported,
+            CttyError::AlreadySessionLeader => ErrorKind::PermissionDenied,
+            CttyError::IOError(e) => return e
+        };
+        // Keep the CttyError as the source so its message isn't lost
+        Error::new(kind, e)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CttyError {
+    NotFound,
     SystemDataParseFailure,
+    /// The system refused the request, with the contained errno (EACCES or EPERM)
+    SystemPermissionFailure(i32),
+    /// The platform has no notion of a controlling tty
+    Unsupported,
+    /// A syscall failed with the contained errno
+    IOError(i32)
+}
+
+#[cfg(not(feature = "std"))]
+impl fmt::Display for CttyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CttyError::NotFound => write!(f, "Controlling TTY for this process not found"),
+            CttyError::SystemDataParseFailure => {
+                write!(f, "System returned invalid data when looking up CTTY")
+            },
+            CttyError::SystemPermissionFailure(errno) => {
+                write!(f, "Failed to request CTTY information from system: errno {}", errno)
+            },
+            CttyError::Unsupported => write!(f, "Controlling TTYs are not supported on this platform"),
+            CttyError::IOError(errno) => write!(f, "System call failed with errno {}", errno)
+        }
+    }
+}
+
+/// The category of a CttyError, without its payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CttyErrorKind {
+    NotFound,
+    ParseFailure,
+    PermissionFailure,
+    Io,
+    Unsupported
+}
+
+impl CttyError {
+    /// Returns which category of error this is
+    ///
+    /// AlreadySessionLeader counts as a PermissionFailure, as setsid refuses it with
+    /// EPERM.
+    pub const fn kind(&self) -> CttyErrorKind {
+        match *self {
+            CttyError::NotFound => CttyErrorKind::NotFound,
+            #[cfg(feature = "std")]
+            CttyError::DeviceNotInDev => CttyErrorKind::NotFound,
+            #[cfg(feature = "std")]
+            CttyError::DevptsNotMounted => CttyErrorKind::NotFound,
+            CttyError::SystemDataParseFailure => CttyErrorKind::ParseFailure,
+            CttyError::SystemPermissionFailure(_) => CttyErrorKind::PermissionFailure,
+            #[cfg(feature = "std")]
+            CttyError::AlreadySessionLeader => CttyErrorKind::PermissionFailure,
+            CttyError::Unsupported => CttyErrorKind::Unsupported,
+            CttyError::IOError(_) => CttyErrorKind::Io
+        }
+    }
+}
+
+/// A device number (dev_t), such as the one identifying a controlling tty
+///
+/// Displays as major:minor, e.g. 136:3 for /dev/pts/3 on Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Dev(pub u64);
+
+impl From<u64> for Dev {
+    fn from(dev: u64) -> Dev {
+        Dev(dev)
+    }
+}
+
+impl From<Dev> for u64 {
+    fn from(dev: Dev) -> u64 {
+        dev.0
+    }
+}
 
-    #[error("Failed to request CTTY information from system")]
-    SystemPermissionFailure,
+impl fmt::Display for Dev {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // There's no major/minor split to show on Haiku, the Hurd, Redox, or unsupported
+        // platforms
+        #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+                      target_os = "dragonfly", target_os = "macos", target_os = "netbsd",
+                      target_os = "openbsd", target_os = "illumos", target_os = "solaris",
+                      target_os = "aix")))]
+        return write!(f, "{}", self.0);
 
-    #[error(transparent)]
-    IOError(#[from] std::io::Error)
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+                  target_os = "dragonfly", target_os = "macos", target_os = "netbsd",
+                  target_os = "openbsd", target_os = "illumos", target_os = "solaris",
+                  target_os = "aix"))]
+        write!(f, "{}:{}", major(*self), minor(*self))
+    }
+}
+
+/// The kind of terminal a controlling tty is, as far as its device can tell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CttyKind {
+    /// A virtual console on the local display, with its number (e.g. 2 for /dev/tty2)
+    VirtualConsole(u32),
+    /// A serial line
+    SerialConsole,
+    /// A pseudo tty (as used by ssh, tmux, etc.), with its number (e.g. 3 for
+    /// /dev/pts/3)
+    Pseudo(u32),
+    /// Anything else, including /dev/console and terminals that couldn't be identified
+    Other
 }
 
-#[cfg(target_os = "linux")]
+// Android shares the Linux /proc interface, with a few differences noted below.
+#[cfg(any(target_os = "linux", target_os = "android"))]
 mod linux {
-    use std::fs::File;
+    #[cfg(feature = "std")]
+    use std::ffi::OsStr;
+    #[cfg(feature = "std")]
+    use std::fs::{self, File};
+    #[cfg(feature = "std")]
+    use std::io;
+    #[cfg(feature = "std")]
     use std::io::prelude::*;
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    use std::collections::HashSet;
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    use std::os::unix::ffi::OsStrExt;
+    #[cfg(feature = "std")]
+    use std::os::unix::fs::FileTypeExt;
+    #[cfg(feature = "std")]
+    use std::os::unix::fs::MetadataExt;
+    #[cfg(feature = "std")]
+    use std::path::{Path, PathBuf};
 
-    use ::CttyError;
+    #[cfg(feature = "std")]
+    use std::str::FromStr;
+    #[cfg(feature = "std")]
+    use std::sync::atomic::{AtomicU32, Ordering};
+    #[cfg(feature = "tokio")]
+    use std::future::Future;
+    #[cfg(feature = "tokio")]
+    use std::pin::Pin;
+    #[cfg(feature = "tokio")]
+    use std::task::{Context, Poll};
+
+    #[cfg(not(feature = "std"))]
+    use core::str::FromStr;
+    #[cfg(not(feature = "std"))]
+    use core::sync::atomic::{AtomicU32, Ordering};
+    #[cfg(not(feature = "std"))]
+    use alloc::string::String;
+    #[cfg(not(feature = "std"))]
+    use alloc::vec::Vec;
+
+    use ::{CttyError, CttyKind, Dev};
+
+    #[cfg(feature = "tokio")]
+    extern crate tokio;
+
+    extern crate libc;
+    use self::libc::{c_char, c_uint};
+
+    // Bionic's libc doesn't export TIOCGDEV, so define it here (_IOR('T', 0x32, unsigned int))
+    #[cfg(target_os = "android")]
+    const TIOCGDEV: self::libc::c_int = 0x8004_5432u32 as self::libc::c_int;
+    #[cfg(target_os = "linux")]
+    use self::libc::TIOCGDEV;
 
-    extern crate glob;
-    use self::glob::glob;
+    // Paths to proc files are only representable as &str without std
+    #[cfg(feature = "std")]
+    type ProcPath = Path;
+    #[cfg(not(feature = "std"))]
+    type ProcPath = str;
 
-    extern crate nix;
-    use self::nix::sys::stat::stat;
+    /// Returns the dev_t corresponding to the current process's controlling tty
+    #[cfg(feature = "std")]
+    pub fn get_ctty_dev() -> Result<Dev, CttyError> {
+        get_ctty_dev_from_proc(Path::new("/proc"))
+    }
 
     /// Returns the dev_t corresponding to the current process's controlling tty
-    pub fn get_ctty_dev() -> Result<u64, CttyError> {
+    #[cfg(not(feature = "std"))]
+    pub fn get_ctty_dev() -> Result<Dev, CttyError> {
+        get_ctty_dev_or_fallback("/proc/self/stat")
+    }
+
+    /// Returns the dev_t corresponding to the current process's controlling tty, using
+    /// the procfs mounted at proc_root instead of /proc
+    #[cfg(feature = "std")]
+    pub fn get_ctty_dev_from_proc(proc_root: &Path) -> Result<Dev, CttyError> {
+        get_ctty_dev_or_fallback(&proc_root.join("self/stat"))
+    }
+
+    /// Reads the ctty's device id from the given stat file, falling back to /dev/tty
+    /// if the file doesn't exist (e.g. /proc isn't mounted in a minimal container)
+    fn get_ctty_dev_or_fallback(stat_path: &ProcPath) -> Result<Dev, CttyError> {
         // /proc/self/stat contains the ctty's device id in field 7
-        // Open it and read its contents to a string
-        let mut stat_f = File::open("/proc/self/stat")?;
-        let mut stat = String::new();
-        stat_f.read_to_string(&mut stat)?;
-
-        // Start looking at the string two positions after the last ')'
-        // This is because the data inside the () may contain spaces
-        let mut start_idx = stat.rfind(')').unwrap_or(0);
-        if start_idx == 0 {
-            return Err(CttyError::SystemDataParseFailure);
+        trace!(path = ?stat_path, "reading stat file");
+        match read_stat_file(stat_path) {
+            Ok(stat) => parse_ctty_dev_from_stat(&stat),
+            Err(ref e) if is_not_found(e) => {
+                debug!(path = ?stat_path, "stat file not found, falling back to /dev/tty");
+                get_ctty_dev_from_dev_tty()
+            },
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Returns the dev_t corresponding to the current process's controlling tty
+    /// by opening /dev/tty
+    pub(crate) fn get_ctty_dev_from_dev_tty() -> Result<Dev, CttyError> {
+        use self::libc::{O_CLOEXEC, O_NOCTTY, O_RDWR};
+
+        let fd = retry_eintr(|| unsafe {
+            self::libc::open(b"/dev/tty\0".as_ptr() as *const c_char, O_RDWR | O_NOCTTY | O_CLOEXEC)
+        });
+        if fd < 0 {
+            return Err(match errno() {
+                // Opening /dev/tty fails with ENXIO when there's no ctty
+                self::libc::ENXIO => CttyError::NotFound,
+                e => map_errno(e)
+            });
+        }
+
+        // fstat would only report /dev/tty's own dev_t, so ask the kernel for the
+        // underlying device instead. It comes back in the same encoding as tty_nr
+        let mut tty_nr: c_uint = 0;
+        let res = unsafe { self::libc::ioctl(fd, TIOCGDEV, &mut tty_nr) };
+        let ioctl_errno = errno();
+        unsafe { self::libc::close(fd) };
+        if res < 0 {
+            return Err(map_errno(ioctl_errno));
         }
-        start_idx += 2;
-        
-        // Split by whitespace into array to easily access indices
-        let values_str = &stat[start_idx..];
-        let mut values = values_str.split_whitespace();
 
-        // Extract 5th field from start (represented as i32)
-        let dev = values.nth(4).ok_or(CttyError::SystemDataParseFailure)?;
-        let dev_int = dev.parse::<i32>().map_err(|_| CttyError::SystemDataParseFailure)?;
-        
-        // Cast result to u64 and return
-        Ok(dev_int as u64)
+        Ok(decode_tty_nr(tty_nr))
     }
 
-    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t
-    pub fn get_path_for_dev(dev: u64) -> Result<String, CttyError> {
-        // Check all devices in /dev/pts/* and /dev/tty* for a match 
-        let patterns = ["/dev/pts/*", "/dev/tty"];
-
-        for i in 0..patterns.len() {
-            for entry in glob(patterns[i]).unwrap() {
-                let path = match entry {
-                    Ok(p) => p,
-                    Err(_) => { // Silently continue
-                        continue;
-                    }
-                };
+    /// Returns the dev_t corresponding to the controlling tty of the process with the given pid
+    ///
+    /// Fails with `CttyError::NotFound` if there's no such process, and with
+    /// `CttyError::SystemPermissionFailure` if /proc hides it from the caller (as with
+    /// the hidepid mount option).
+    pub fn get_ctty_dev_for_pid(pid: u32) -> Result<Dev, CttyError> {
+        ctty_dev_from_pid_stat(format!("/proc/{}/stat", pid).as_ref())
+    }
 
-                // See if this device matches the request
-                let stat = match stat(&path) {
-                    Ok(s) => s,
-                    Err(_) => { // Silently continue
-                        continue;
-                    }
-                };
+    /// Reads the ctty out of another process's stat file, which can vanish at any point
+    fn ctty_dev_from_pid_stat(stat_path: &ProcPath) -> Result<Dev, CttyError> {
+        let stat = match read_stat_file(stat_path) {
+            // The process doesn't exist (or has already exited)
+            Err(ref e) if is_not_found(e) => return Err(CttyError::NotFound),
+            res => res?
+        };
+        parse_ctty_dev_from_stat(&stat)
+    }
 
-                if dev == stat.st_rdev {
-                    // Found device, return it
-                    return Ok(String::from(path.to_str().unwrap()));
-                }
+    /// Returns the dev_t corresponding to the controlling tty of the session with the
+    /// given id
+    ///
+    /// Every process in a session shares its ctty, so this asks the session leader,
+    /// whose pid is the sid. Fails with `CttyError::NotFound` if the session has no
+    /// ctty, or if the leader has exited, including when its pid has since been reused
+    /// outside the session.
+    pub fn get_ctty_dev_for_session(sid: i32) -> Result<Dev, CttyError> {
+        if sid <= 0 {
+            return Err(CttyError::NotFound);
+        }
+        let stat = match read_stat_file(format!("/proc/{}/stat", sid).as_ref()) {
+            Err(ref e) if is_not_found(e) => return Err(CttyError::NotFound),
+            res => parse_proc_stat(&res?)?
+        };
+        // Not the leader (its pid was reused), or a leader without a ctty
+        if stat.session != sid || stat.tty_nr == 0 {
+            return Err(CttyError::NotFound);
+        }
+        Ok(decode_tty_nr(stat.tty_nr as u32))
+    }
+
+    /// Returns the dev_t corresponding to the controlling tty of the current process's parent
+    ///
+    /// Fails with `CttyError::NotFound` if the parent has no ctty, as on the other
+    /// platforms.
+    #[cfg_attr(not(feature = "std"), allow(clippy::useless_asref))] // ProcPath is str without std
+    pub fn get_parent_ctty_dev() -> Result<Dev, CttyError> {
+        let stat = parse_proc_stat(&read_stat_file("/proc/self/stat".as_ref())?)?;
+        get_ctty_dev_for_pid(stat.ppid as u32)
+    }
+
+    /// Returns whether the current process is in its controlling tty's foreground
+    /// process group, so it can read from the terminal without being stopped
+    ///
+    /// Fails with `CttyError::NotFound` if there's no ctty.
+    #[cfg_attr(not(feature = "std"), allow(clippy::useless_asref))] // ProcPath is str without std
+    pub fn is_foreground() -> Result<bool, CttyError> {
+        let stat = parse_proc_stat(&read_stat_file("/proc/self/stat".as_ref())?)?;
+        in_foreground(&stat)
+    }
+
+    /// Checks whether the process a stat file describes is in the foreground
+    fn in_foreground(stat: &ProcStat) -> Result<bool, CttyError> {
+        // tpgid is -1 without a ctty
+        if stat.tty_nr == 0 || stat.tpgid == -1 {
+            return Err(CttyError::NotFound);
+        }
+        Ok(stat.pgrp == stat.tpgid)
+    }
+
+    /// Returns the dev_t corresponding to the current process's controlling tty, read
+    /// from /proc/thread-self rather than /proc/self
+    ///
+    /// The controlling tty belongs to the whole session, so every thread sees the same
+    /// one and this always agrees with get_ctty_dev. It only changes which proc file is
+    /// read, for tools that audit per-thread /proc access. /proc/thread-self only
+    /// exists since Linux 3.17, so older kernels fall back to get_ctty_dev.
+    #[cfg_attr(not(feature = "std"), allow(clippy::useless_asref))] // ProcPath is str without std
+    pub fn get_ctty_dev_thread() -> Result<Dev, CttyError> {
+        match read_stat_file("/proc/thread-self/stat".as_ref()) {
+            Ok(stat) => parse_ctty_dev_from_stat(&stat),
+            Err(ref e) if is_not_found(e) => get_ctty_dev(),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Returns the current process's tty_nr exactly as /proc/self/stat reports it, in
+    /// the kernel's encoding rather than as a dev_t (0 if there's no controlling tty)
+    ///
+    /// This is the value to compare against tty_nr from other stat files, e.g. when
+    /// reproducing ps's TTY column.
+    #[cfg_attr(not(feature = "std"), allow(clippy::useless_asref))] // ProcPath is str without std
+    pub fn get_raw_tty_nr() -> Result<u32, CttyError> {
+        let stat = parse_proc_stat(&read_stat_file("/proc/self/stat".as_ref())?)?;
+        Ok(stat.tty_nr as u32)
+    }
+
+    /// Returns the dev_t corresponding to the current process's controlling tty,
+    /// without blocking the async executor
+    ///
+    /// This behaves like an `async fn`; it's written out by hand since the crate
+    /// targets the 2015 edition.
+    #[cfg(feature = "tokio")]
+    pub fn get_ctty_dev_async() -> impl Future<Output = Result<Dev, CttyError>> {
+        CttyDevFuture::Reading(Box::pin(self::tokio::fs::read_to_string("/proc/self/stat")))
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given
+    /// dev_t, scanning /dev on tokio's blocking thread pool
+    #[cfg(feature = "tokio")]
+    pub fn get_path_for_dev_async(dev: Dev) -> impl Future<Output = Result<String, CttyError>> {
+        BlockingTask::Start(Some(move || ::get_path_for_dev(dev)))
+    }
+
+    /// Future returned by get_ctty_dev_async, which reads /proc/self/stat and falls back
+    /// to /dev/tty the same way get_ctty_dev_or_fallback does
+    #[cfg(feature = "tokio")]
+    enum CttyDevFuture {
+        Reading(Pin<Box<dyn Future<Output = io::Result<String>> + Send>>),
+        Fallback(BlockingTask<fn() -> Result<Dev, CttyError>, Dev>)
+    }
+
+    #[cfg(feature = "tokio")]
+    impl Future for CttyDevFuture {
+        type Output = Result<Dev, CttyError>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            let stat = match *self {
+                CttyDevFuture::Reading(ref mut read) => match read.as_mut().poll(cx) {
+                    Poll::Ready(res) => res.map_err(map_io_error),
+                    Poll::Pending => return Poll::Pending
+                },
+                CttyDevFuture::Fallback(ref mut task) => return Pin::new(task).poll(cx)
+            };
+
+            match stat {
+                Ok(stat) => Poll::Ready(parse_ctty_dev_from_stat(&stat)),
+                Err(ref e) if is_not_found(e) => {
+                    let fallback = get_ctty_dev_from_dev_tty as fn() -> Result<Dev, CttyError>;
+                    *self = CttyDevFuture::Fallback(BlockingTask::Start(Some(fallback)));
+                    self.poll(cx)
+                },
+                Err(e) => Poll::Ready(Err(e))
             }
         }
+    }
 
-        Err(CttyError::NotFound)
+    /// A lookup run on tokio's blocking thread pool. Like an `async fn`, nothing is
+    /// spawned until the first poll, so the future can be created outside a runtime
+    #[cfg(feature = "tokio")]
+    enum BlockingTask<F, T> {
+        Start(Option<F>),
+        Running(self::tokio::task::JoinHandle<Result<T, CttyError>>)
     }
-}
-#[cfg(target_os = "linux")]
-pub use linux::*;
 
-// For FreeBSD and macOS, it's probably not worth it to recreate the kinfo_proc struct
-// in Rust and use FFI bindings to call sysctl, so I'm instead using a small C wrapper.
-#[cfg(any(target_os = "freebsd", target_os = "macos"))]
-mod bsd {
-    use std::ffi::CStr;
+    #[cfg(feature = "tokio")]
+    impl<F, T> Future for BlockingTask<F, T>
+        where F: FnOnce() -> Result<T, CttyError> + Send + Unpin + 'static, T: Send + 'static {
+        type Output = Result<T, CttyError>;
 
-    use ::CttyError;
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            if let BlockingTask::Start(ref mut f) = *self {
+                let f = f.take().expect("BlockingTask polled after completion");
+                *self = BlockingTask::Running(self::tokio::task::spawn_blocking(f));
+            }
 
-    extern crate libc;
-    use self::libc::{S_IFCHR, c_int, mode_t, dev_t, c_char};
+            let task = match *self {
+                BlockingTask::Running(ref mut task) => task,
+                BlockingTask::Start(_) => unreachable!()
+            };
+            match Pin::new(task).poll(cx) {
+                Poll::Ready(Ok(res)) => Poll::Ready(res),
+                // The task panicked or the runtime is shutting down
+                Poll::Ready(Err(e)) => Poll::Ready(Err(CttyError::IOError(io::Error::other(e)))),
+                Poll::Pending => Poll::Pending
+            }
+        }
+    }
 
-    extern "C" {
-        // Provided by wrapper (see bsd.c)
-        fn _get_ctty_dev() -> u64;
+    /// Returns the dev_t of the controlling tty recorded in the given
+    /// /proc/<pid>/stat contents
+    ///
+    /// This runs the same parser as get_ctty_dev without touching the real /proc,
+    /// e.g. to check stat contents captured elsewhere.
+    #[cfg(feature = "std")]
+    pub fn get_ctty_dev_from_reader<R: Read>(reader: R) -> Result<Dev, CttyError> {
+        parse_ctty_dev_from_stat(&read_stat(reader)?)
+    }
 
-        // Provided by system libc
-        fn devname_r(dev: dev_t, type_: mode_t, buf: *mut u8, len: c_int) -> *mut c_char;
+    /// Opens a /proc/<pid>/stat file and reads its contents to a string
+    #[cfg(feature = "std")]
+    fn read_stat_file(path: &Path) -> Result<String, CttyError> {
+        read_stat(File::open(path).map_err(map_io_error)?)
     }
-    
 
-    /// Returns the dev_t corresponding to the current process's controlling tty
-    pub fn get_ctty_dev() -> Result<u64, CttyError> {
-        let res = unsafe { _get_ctty_dev() };
-        if res == 0 {
+    /// Reads stat file contents to a string
+    ///
+    /// std already retries read when it's interrupted by a signal.
+    #[cfg(feature = "std")]
+    fn read_stat<R: Read>(mut reader: R) -> Result<String, CttyError> {
+        let mut stat = Vec::new();
+        reader.read_to_end(&mut stat).map_err(map_io_error)?;
+        String::from_utf8(stat).map_err(|_| CttyError::SystemDataParseFailure)
+    }
+
+    /// Opens a /proc/<pid>/stat file and reads its contents to a string
+    #[cfg(not(feature = "std"))]
+    fn read_stat_file(path: &str) -> Result<String, CttyError> {
+        use self::libc::{c_void, O_CLOEXEC, O_RDONLY};
+
+        // open wants a NUL-terminated path
+        let mut c_path = Vec::from(path.as_bytes());
+        c_path.push(0);
+
+        let fd = retry_eintr(|| unsafe {
+            self::libc::open(c_path.as_ptr() as *const c_char, O_RDONLY | O_CLOEXEC)
+        });
+        if fd < 0 {
+            return Err(map_errno(errno()));
+        }
+
+        // Read until EOF, making sure the fd gets closed even on failure
+        let mut stat = Vec::new();
+        let mut buf = [0u8; 512];
+        let res = loop {
+            let n = retry_eintr(|| unsafe {
+                self::libc::read(fd, buf.as_mut_ptr() as *mut c_void, buf.len())
+            });
+            if n < 0 {
+                break Err(map_errno(errno()));
+            } else if n == 0 {
+                break Ok(());
+            }
+            stat.extend_from_slice(&buf[..n as usize]);
+        };
+        unsafe { self::libc::close(fd) };
+        res?;
+
+        String::from_utf8(stat).map_err(|_| CttyError::SystemDataParseFailure)
+    }
+
+    /// Converts an IO error from reading /proc into a CttyError, singling out permission failures
+    #[cfg(feature = "std")]
+    fn map_io_error(e: io::Error) -> CttyError {
+        match e.kind() {
+            // An error that only carries a kind is reported as EACCES
+            io::ErrorKind::PermissionDenied => {
+                CttyError::SystemPermissionFailure(e.raw_os_error().unwrap_or(self::libc::EACCES))
+            },
+            _ => CttyError::IOError(e)
+        }
+    }
+
+    /// Converts an errno from a failed syscall into a CttyError, singling out permission failures
+    #[cfg(feature = "std")]
+    fn map_errno(errno: i32) -> CttyError {
+        map_io_error(io::Error::from_raw_os_error(errno))
+    }
+
+    /// Converts an errno from a failed syscall into a CttyError, singling out permission failures
+    #[cfg(not(feature = "std"))]
+    fn map_errno(errno: i32) -> CttyError {
+        match errno {
+            self::libc::EACCES | self::libc::EPERM => CttyError::SystemPermissionFailure(errno),
+            _ => CttyError::IOError(errno)
+        }
+    }
+
+    /// Returns the calling thread's errno
+    #[cfg(target_os = "linux")]
+    fn errno() -> i32 {
+        unsafe { *self::libc::__errno_location() }
+    }
+
+    /// Returns the calling thread's errno
+    #[cfg(target_os = "android")]
+    fn errno() -> i32 {
+        unsafe { *self::libc::__errno() }
+    }
+
+    /// Repeats a raw syscall for as long as it fails with EINTR, which happens when a
+    /// signal arrives mid-call
+    fn retry_eintr<T, F>(mut f: F) -> T where T: PartialOrd + From<i8>, F: FnMut() -> T {
+        loop {
+            let res = f();
+            if res >= T::from(0) || errno() != self::libc::EINTR {
+                return res;
+            }
+        }
+    }
+
+    /// Repeats an IO operation for as long as it's interrupted by a signal
+    #[cfg(feature = "std")]
+    fn retry_interrupted<T, F>(mut f: F) -> io::Result<T> where F: FnMut() -> io::Result<T> {
+        loop {
+            match f() {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => return res
+            }
+        }
+    }
+
+    /// Checks whether an error from read_stat_file means the file doesn't exist
+    ///
+    /// Reading the stat file of a process that exited after it was opened fails with
+    /// ESRCH, which counts too.
+    #[cfg(feature = "std")]
+    fn is_not_found(e: &CttyError) -> bool {
+        match *e {
+            CttyError::IOError(ref e) => {
+                e.kind() == io::ErrorKind::NotFound || e.raw_os_error() == Some(self::libc::ESRCH)
+            },
+            _ => false
+        }
+    }
+
+    /// Checks whether an error from read_stat_file means the file doesn't exist
+    ///
+    /// Reading the stat file of a process that exited after it was opened fails with
+    /// ESRCH, which counts too.
+    #[cfg(not(feature = "std"))]
+    fn is_not_found(e: &CttyError) -> bool {
+        match *e {
+            CttyError::IOError(errno) => errno == self::libc::ENOENT || errno == self::libc::ESRCH,
+            _ => false
+        }
+    }
+
+    /// The leading fields of a /proc/<pid>/stat file, up to and including tpgid
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ProcStat {
+        pub pid: i32,
+        /// The executable name, without the surrounding parentheses
+        pub comm: String,
+        pub state: char,
+        pub ppid: i32,
+        pub pgrp: i32,
+        pub session: i32,
+        /// The controlling tty, still in the kernel's encoding (0 if there is none)
+        pub tty_nr: u64,
+        /// The foreground process group of the controlling tty, or -1
+        pub tpgid: i32
+    }
+
+    /// Parses the contents of a /proc/<pid>/stat file
+    pub fn parse_proc_stat(stat: &str) -> Result<ProcStat, CttyError> {
+        // Reading a stat file can come up empty, e.g. for a process being torn down
+        if stat.trim().is_empty() {
+            return Err(CttyError::SystemDataParseFailure);
+        }
+
+        // comm is wrapped in parens but may itself contain spaces and parens, so it
+        // runs from the first '(' to the last ')'
+        let start_idx = stat.find('(').ok_or(CttyError::SystemDataParseFailure)?;
+        let end_idx = stat.rfind(')').ok_or(CttyError::SystemDataParseFailure)?;
+        if end_idx < start_idx {
+            return Err(CttyError::SystemDataParseFailure);
+        }
+
+        // Split the rest by whitespace to easily access fields. Counting fields
+        // rather than bytes means it doesn't matter how much space follows the ')'.
+        // The parens are ASCII, so slicing next to them always lands on a char
+        // boundary, whatever comm contains
+        let mut values = stat[end_idx + 1..].split_whitespace();
+        let state = values.next().and_then(|s| s.chars().next());
+
+        Ok(ProcStat {
+            pid: parse_field(Some(&stat[..start_idx]))?,
+            comm: String::from(&stat[start_idx + 1..end_idx]),
+            state: state.ok_or(CttyError::SystemDataParseFailure)?,
+            ppid: parse_field(values.next())?,
+            pgrp: parse_field(values.next())?,
+            session: parse_field(values.next())?,
+            // The kernel prints tty_nr as a signed int, so parse it wide and keep
+            // the low 32 bits to avoid sign extension
+            tty_nr: u64::from(parse_field::<i64>(values.next())? as u32),
+            tpgid: parse_field(values.next())?
+        })
+    }
+
+    /// Parses a single whitespace-trimmed field of a stat file
+    fn parse_field<T: FromStr>(field: Option<&str>) -> Result<T, CttyError> {
+        field.and_then(|f| f.trim().parse().ok()).ok_or(CttyError::SystemDataParseFailure)
+    }
+
+    /// Extracts the ctty's device id from the contents of a /proc/<pid>/stat file
+    ///
+    /// A tty_nr of 0 means the process has no ctty, which fails with
+    /// `CttyError::NotFound` as it does on the other platforms.
+    pub fn parse_ctty_dev_from_stat(stat: &str) -> Result<Dev, CttyError> {
+        let stat = parse_proc_stat(stat)?;
+        if stat.tty_nr == 0 {
             return Err(CttyError::NotFound);
         }
-        Ok(res)
+        let dev = decode_tty_nr(stat.tty_nr as u32);
+        trace!(tty_nr = stat.tty_nr, %dev, "decoded tty_nr");
+        Ok(dev)
+    }
+
+    /// Converts a tty_nr in the kernel's old-style encoding into a dev_t
+    fn decode_tty_nr(tty_nr: u32) -> Dev {
+        // The major number lives in bits 8-19, and the minor number is split
+        // between the low 8 bits and bits 20-31
+        let major = (tty_nr >> 8) & 0xfff;
+        let minor = (tty_nr & 0xff) | ((tty_nr >> 12) & 0xfff00);
+        makedev(u64::from(major), u64::from(minor))
+    }
+
+    /// Returns the major number of the given dev_t
+    ///
+    /// On Linux, major 4 covers the virtual consoles (minors 0-63) and serial ports
+    /// (minors 64 and up), and majors 136-143 are normally UNIX98 pseudo ttys.
+    pub fn major(dev: Dev) -> u32 {
+        (((dev.0 >> 32) & 0xffff_f000) | ((dev.0 >> 8) & 0x0000_0fff)) as u32
+    }
+
+    /// Returns the minor number of the given dev_t
+    ///
+    /// On Linux, this is the console number for virtual consoles and, combined with
+    /// the major, the N in /dev/pts/N for pseudo ttys.
+    pub fn minor(dev: Dev) -> u32 {
+        (((dev.0 >> 12) & 0xffff_ff00) | (dev.0 & 0x0000_00ff)) as u32
+    }
+
+    /// Returns whether the given dev_t is a pseudo tty (as used by ssh, tmux, etc.)
+    pub fn is_pty(dev: Dev) -> bool {
+        let first = pts_major();
+        (first..=first + 7).contains(&major(dev))
+    }
+
+    /// The first major of the UNIX98 pty slaves, once read from /proc/devices, or 0
+    static PTS_MAJOR: AtomicU32 = AtomicU32::new(0);
+
+    /// Returns the first of the 8 majors that UNIX98 pty slaves are allocated from
+    ///
+    /// This is 136 unless /proc/devices says the kernel registered the pts driver
+    /// elsewhere. It's only read once, since the assignment can't change while the
+    /// system is running.
+    #[cfg_attr(not(feature = "std"), allow(clippy::useless_asref))] // ProcPath is str without std
+    fn pts_major() -> u32 {
+        match PTS_MAJOR.load(Ordering::Relaxed) {
+            0 => {
+                let first = read_stat_file("/proc/devices".as_ref()).ok()
+                    .and_then(|devices| parse_pts_major(&devices))
+                    .unwrap_or(136);
+                PTS_MAJOR.store(first, Ordering::Relaxed);
+                first
+            },
+            first => first
+        }
+    }
+
+    /// Finds the pts driver's first major in the character devices listed by
+    /// /proc/devices
+    ///
+    /// Older kernels with 8-bit minors list each of its majors, and newer ones only
+    /// the first.
+    fn parse_pts_major(devices: &str) -> Option<u32> {
+        devices.lines()
+            .skip_while(|line| line.trim() != "Character devices:")
+            .skip(1)
+            .take_while(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let major = fields.next()?.parse::<u32>().ok()?;
+                match fields.next() {
+                    Some("pts") if major != 0 => Some(major),
+                    _ => None
+                }
+            })
+            .min()
+    }
+
+    /// Returns the kind of terminal the given dev_t is, going by the device numbers
+    /// the kernel assigns to each driver
+    pub fn classify_dev(dev: Dev) -> CttyKind {
+        match (major(dev), minor(dev)) {
+            // tty0 is whichever virtual console is active
+            (4, min @ 0..=63) => CttyKind::VirtualConsole(min),
+            (4, _) => CttyKind::SerialConsole,
+            // Before 20-bit minors, ptys 256 and up spilled over into the next 7 majors
+            (maj, min) if is_pty(dev) => CttyKind::Pseudo((maj - pts_major()) * 256 + min),
+            // Legacy BSD-style pty slaves (ttyp0 and so on)
+            (3, min) => CttyKind::Pseudo(min),
+            // ttyACM*, ttyUSB*, and ttyAMA* (and other low-density serial ports)
+            (166, _) | (188, _) | (204, _) => CttyKind::SerialConsole,
+            _ => CttyKind::Other
+        }
+    }
+
+    /// Builds a dev_t from its major and minor numbers, using glibc's encoding
+    fn makedev(major: u64, minor: u64) -> Dev {
+        Dev(((major & 0xffff_f000) << 32) | ((major & 0x0000_0fff) << 8) |
+            ((minor & 0xffff_ff00) << 12) | (minor & 0x0000_00ff))
     }
 
     /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t
-    pub fn get_path_for_dev(dev: u64) -> Result<String, CttyError> {
-        let mut buf: Vec<u8> = Vec::with_capacity(255);
-        unsafe {
-            let res: *mut c_char = devname_r(dev as dev_t, S_IFCHR, buf.as_mut_ptr(), 255);
-            // On failure, result will be NULL, &'?', or &'#' depending on OS
-            if res.is_null() || *res as u8 == b'?' || *res as u8 == b'#' {
-                return Err(CttyError::NotFound);
-            }
+    #[cfg(feature = "std")]
+    pub fn get_path_for_dev_buf(dev: Dev) -> Result<PathBuf, CttyError> {
+        get_path_for_dev_in(dev, Path::new("/dev"))
+    }
 
-            // Convert the buffer into an owned string
-            let res_owned = CStr::from_ptr(res).to_string_lossy().into_owned();
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t,
+    /// searching dev_root instead of /dev
+    ///
+    /// A relative dev_root is used as given, so it's resolved against the current
+    /// directory and the returned path is relative too. If dev is the current
+    /// process's ctty but has no node there, this fails with
+    /// `CttyError::DeviceNotInDev` rather than `CttyError::NotFound`.
+    ///
+    /// A subdirectory that can't be read, such as a locked-down pts, is skipped. On
+    /// Linux, if nothing was found and dev_root itself can't be listed, that error is
+    /// returned instead (e.g. `CttyError::SystemPermissionFailure`).
+    #[cfg(feature = "std")]
+    pub fn get_path_for_dev_in(dev: Dev, dev_root: &Path) -> Result<PathBuf, CttyError> {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!("scan_dev", %dev).entered();
+        let path = matching_devs(dev, dev_root).next();
+        debug!(?path, "finished scanning for device");
+        path.ok_or_else(|| missing_dev_error(dev, dev_root))
+    }
 
-            // Append /dev/ to the beginning and return it
-            Ok(format!("{}{}", "/dev/", res_owned))
+    /// Returns the full paths of every tty or pseudo tty that corresponds with the
+    /// given dev_t, in the order they were found
+    #[cfg(feature = "std")]
+    pub fn get_paths_for_dev(dev: Dev) -> Result<Vec<PathBuf>, CttyError> {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!("scan_dev", %dev).entered();
+        let paths: Vec<PathBuf> = matching_devs(dev, Path::new("/dev")).collect();
+        debug!(?paths, "finished scanning for device");
+        if paths.is_empty() {
+            return Err(missing_dev_error(dev, Path::new("/dev")));
         }
+        Ok(paths)
     }
-}
-#[cfg(any(target_os = "freebsd", target_os = "macos"))]
-pub use bsd::*;
 
-#[cfg(test)]
-mod tests {
-    use std::error::Error;
-    use ::get_path_for_dev;
-    use ::get_ctty_dev;
+    /// Returns the error for when no device node matches dev
+    ///
+    /// A PID namespace sees the same ctty as the host, but the mount namespace it's
+    /// in may have a /dev of its own without the host's devpts. So if dev is known to
+    /// be the current process's ctty, the device exists and it's only the node that's
+    /// missing. Before either, a pseudo tty can't be found at all if dev_root has no
+    /// devpts, which shows up as a pts directory that's absent or empty (a mounted
+    /// devpts always holds at least ptmx).
+    #[cfg(feature = "std")]
+    fn missing_dev_error(dev: Dev, dev_root: &Path) -> CttyError {
+        // Nothing under a dev_root that can't be listed was searched at all. Android's
+        // SELinux policy normally denies listing /dev, so that's expected there
+        #[cfg(target_os = "linux")]
+        {
+            if let Err(e) = fs::read_dir(dev_root) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return map_io_error(e);
+                }
+            }
+        }
+        if is_pty(dev) && !has_devpts(dev_root) {
+            return CttyError::DevptsNotMounted;
+        }
+        match get_ctty_dev() {
+            Ok(ctty) if ctty == dev && dev != Dev(0) => CttyError::DeviceNotInDev,
+            _ => CttyError::NotFound
+        }
+    }
 
-    #[test]
-    fn test_get_ctty_dev() -> Result<(), Box<dyn Error>> {
-        let dev = get_ctty_dev().unwrap();
-        dbg!(dev);
-        let path = get_path_for_dev(dev)?;
-        dbg!(path);
+    /// Checks whether dev_root/pts looks like a mounted devpts
+    ///
+    /// Only a pts directory that's missing or empty counts as unmounted. One that can't
+    /// be listed (as under Android's SELinux policy) is given the benefit of the doubt.
+    #[cfg(feature = "std")]
+    fn has_devpts(dev_root: &Path) -> bool {
+        match fs::read_dir(dev_root.join("pts")) {
+            Ok(mut entries) => entries.next().is_some(),
+            Err(e) => e.kind() != io::ErrorKind::NotFound
+        }
+    }
+
+    /// Returns the name of the tty or pseudo tty that corresponds with the given dev_t,
+    /// relative to /dev/ (e.g. pts/3), as shown by `who` and `ps`
+    #[cfg(feature = "std")]
+    pub fn get_name_for_dev(dev: Dev) -> Result<String, CttyError> {
+        let path = get_path_for_dev_buf(dev)?;
+        // Every directory searched is under /dev
+        let name = path.strip_prefix("/dev").map_err(|_| CttyError::SystemDataParseFailure)?;
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    /// Returns every device node under dev_root that corresponds with the given dev_t
+    ///
+    /// If nothing matches under /dev, any devpts or devtmpfs mounted elsewhere (as in
+    /// some rootless containers) is searched next. Under WSL, tty_nr often refers to
+    /// a device that doesn't exist anywhere, so if there's still no match and dev is
+    /// the current process's ctty, the path is recovered from ttyname on stdin,
+    /// stdout, or stderr instead. Failing that, /dev/tty itself is used if it refers
+    /// to dev.
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn matching_devs(dev: Dev, dev_root: &Path) -> impl Iterator<Item = PathBuf> {
+        let mut found = walk_devices(dev_root)
+            .filter(move |(_, m)| is_node_for(dev, m))
+            .map(|(path, _)| path)
+            .peekable();
+
+        // Mounts and ttyname always report paths under the real /dev
+        let fallback = match found.peek() {
+            None if dev_root == Path::new("/dev") => relocated_devs(dev),
+            _ => Vec::new()
+        };
+        found.chain(fallback)
+    }
+
+    /// Returns every terminal device node under dev_root, along with its metadata
+    ///
+    /// This is the one walk behind both the lookups and list_terminals: all of
+    /// pts/*, tty*, and console, with each entry stat'd once. A symlink yields the
+    /// node it points to instead, and only the first path to each node is kept.
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn walk_devices(dev_root: &Path) -> impl Iterator<Item = (PathBuf, fs::Metadata)> {
+        let mut seen = HashSet::new();
+        nodes_in_dir(&dev_root.join("pts"), |_| true)
+            .chain(nodes_in_dir(dev_root, |name| is_tty_name(name) || name == "console"))
+            .filter_map(follow_symlink)
+            .filter(move |(path, _)| seen.insert(path.clone()))
+    }
+
+    /// Collects walk_devices, for callers that need more than one answer from /dev
+    ///
+    /// Looking several devices up one at a time stats every node again for each of
+    /// them, so those callers should filter a single scan instead. A lone lookup
+    /// should keep using walk_devices directly, since that stops at the first match.
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    pub(crate) fn scan_devices(dev_root: &Path) -> Vec<(PathBuf, fs::Metadata)> {
+        let nodes: Vec<_> = walk_devices(dev_root).collect();
+        debug!(count = nodes.len(), "scanned device nodes");
+        nodes
+    }
+
+    /// Returns every device node corresponding with the given dev_t that can be found
+    /// outside of /dev, for when scanning /dev turned up nothing
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn relocated_devs(dev: Dev) -> Vec<PathBuf> {
+        if let Ok(mounts) = fs::read_to_string("/proc/mounts") {
+            let paths: Vec<PathBuf> = devs_in_mounts(dev, &mounts).collect();
+            if !paths.is_empty() {
+                return paths;
+            }
+        }
+
+        if dev == Dev(0) || get_ctty_dev().ok() != Some(dev) {
+            return Vec::new();
+        }
+        if is_wsl() {
+            if let Some(path) = (0..3).filter_map(|fd| ::ttyname_of_fd(fd).ok()).next() {
+                return vec![path];
+            }
+        }
+
+        // /dev/tty has a node of its own even where the ctty's is missing, which can
+        // stand in for it
+        match get_ctty_dev_from_dev_tty() {
+            Ok(tty) if tty == dev => vec![PathBuf::from("/dev/tty")],
+            _ => Vec::new()
+        }
+    }
+
+    /// Returns the matching device nodes in each devpts and devtmpfs listed in the
+    /// given /proc/mounts contents, other than the usual ones at /dev/pts and /dev
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn devs_in_mounts(dev: Dev, mounts: &str) -> impl Iterator<Item = PathBuf> + '_ {
+        mounts.lines().filter_map(|line| {
+            // Each line is: source, mount point, fstype, options, dump, pass
+            let mut fields = line.split_whitespace().skip(1);
+            let (dir, fstype) = (fields.next()?, fields.next()?);
+            let dir = unescape_mount_path(dir);
+            match fstype {
+                "devpts" if dir != Path::new("/dev/pts") => Some((dir, true)),
+                "devtmpfs" if dir != Path::new("/dev") => Some((dir, false)),
+                _ => None
+            }
+        }).flat_map(move |(dir, is_devpts)| {
+            trace!(dir = %dir.display(), is_devpts, "scanning relocated mount");
+            let names: fn(&OsStr) -> bool = if is_devpts {
+                |_| true
+            } else {
+                |name| is_tty_name(name) || name == "console"
+            };
+            devs_in_dir(dev, &dir, names).collect::<Vec<_>>()
+        })
+    }
+
+    /// Decodes the octal escapes (e.g. \040 for a space) the kernel uses for
+    /// whitespace and backslashes in /proc/mounts paths
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn unescape_mount_path(path: &str) -> PathBuf {
+        let bytes = path.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let escape = bytes.get(i + 1..i + 4)
+                .filter(|digits| bytes[i] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+                .map(|digits| digits.iter().fold(0u8, |acc, d| acc.wrapping_mul(8) + (d - b'0')));
+            match escape {
+                Some(b) => {
+                    out.push(b);
+                    i += 4;
+                },
+                None => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        PathBuf::from(OsStr::from_bytes(&out))
+    }
+
+    /// Returns every terminal device node in /dev/pts, /dev/tty*, and /dev/console,
+    /// paired with its device number, in the order they were found
+    ///
+    /// Each entry is stat'd once, and anything that isn't a character device is skipped.
+    /// Symlinks are reported as the node they point to, as get_path_for_dev does.
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    pub fn list_terminals() -> Result<Vec<(PathBuf, Dev)>, CttyError> {
+        Ok(scan_devices(Path::new("/dev")).into_iter()
+            .filter(|(_, m)| m.file_type().is_char_device())
+            .map(|(path, m)| (path, Dev(m.rdev())))
+            .collect())
+    }
+
+    /// Returns the terminals from list_terminals ordered by (major, minor), e.g. for a
+    /// deterministic `who`-style listing
+    ///
+    /// This is numeric, so /dev/pts/2 comes before /dev/pts/10. Dev's own ordering
+    /// compares the raw dev_t, which doesn't agree once minors go past 255.
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    pub fn list_terminals_sorted() -> Result<Vec<(PathBuf, Dev)>, CttyError> {
+        let mut terminals = list_terminals()?;
+        sort_by_dev(&mut terminals);
+        Ok(terminals)
+    }
+
+    /// Sorts terminals by (major, minor), keeping the scan order for nodes that share
+    /// a device
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn sort_by_dev(terminals: &mut [(PathBuf, Dev)]) {
+        terminals.sort_by_key(|&(_, dev)| (major(dev), minor(dev)));
+    }
+
+    /// Checks whether a /dev entry is one of the tty* nodes, which cover virtual
+    /// consoles, serial lines, and the /dev/tty multiplexer itself
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn is_tty_name(name: &OsStr) -> bool {
+        name.as_bytes().starts_with(b"tty")
+    }
+
+    /// Returns whether this is running under the Windows Subsystem for Linux
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn is_wsl() -> bool {
+        match fs::read_to_string("/proc/sys/kernel/osrelease") {
+            Ok(release) => is_wsl_release(&release),
+            Err(_) => false
+        }
+    }
+
+    /// Checks whether a kernel release string is one of WSL's, e.g.
+    /// 5.15.90.1-microsoft-standard-WSL2 or 4.4.0-19041-Microsoft
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn is_wsl_release(release: &str) -> bool {
+        release.to_lowercase().contains("microsoft")
+    }
+
+    /// Returns every device node that corresponds with the given dev_t
+    ///
+    /// On Android, SELinux policy usually denies listing or stat-ing most of /dev, so
+    /// only /dev/pts is searched. If that fails but dev is the current process's ctty,
+    /// /dev/tty is returned instead, since it always refers to the ctty.
+    #[cfg(all(feature = "std", target_os = "android"))]
+    fn matching_devs(dev: Dev, dev_root: &Path) -> impl Iterator<Item = PathBuf> {
+        let mut pts = devs_in_dir(dev, &dev_root.join("pts"), |_| true).peekable();
+        let fallback = match pts.peek() {
+            None if get_ctty_dev_from_dev_tty().ok() == Some(dev) => Some(dev_root.join("tty")),
+            _ => None
+        };
+        pts.chain(fallback)
+    }
+
+    /// Returns the character devices in dir accepted by filter whose device is dev,
+    /// making a single lazy pass over the directory
+    ///
+    /// A symlink to a matching device (such as /dev/console on some systems) yields the
+    /// canonical path of the node it points to rather than the link itself.
+    #[cfg(feature = "std")]
+    fn devs_in_dir<'a, F>(dev: Dev, dir: &Path, filter: F) -> impl Iterator<Item = PathBuf> + 'a
+        where F: Fn(&OsStr) -> bool + 'a {
+        nodes_in_dir(dir, filter)
+            .filter_map(follow_symlink)
+            .filter(move |(_, m)| is_node_for(dev, m))
+            .map(|(path, _)| path)
+    }
+
+    /// Checks whether metadata from nodes_in_dir is that of the device node for dev
+    #[cfg(feature = "std")]
+    fn is_node_for(dev: Dev, m: &fs::Metadata) -> bool {
+        // A block device can share a tty's rdev, so the node type has to match too
+        m.file_type().is_char_device() && m.rdev() == dev.0
+    }
+
+    /// Replaces a symlink from nodes_in_dir with the canonical path and metadata of
+    /// what it points to, dropping it if that can't be resolved
+    #[cfg(feature = "std")]
+    fn follow_symlink((path, m): (PathBuf, fs::Metadata)) -> Option<(PathBuf, fs::Metadata)> {
+        if !m.file_type().is_symlink() {
+            return Some((path, m));
+        }
+        let target = fs::canonicalize(&path).ok()?;
+        let m = retry_interrupted(|| fs::metadata(&target)).ok()?;
+        trace!(path = %path.display(), target = %target.display(), "followed symlink");
+        Some((target, m))
+    }
+
+    /// Returns the entries of dir accepted by filter along with their metadata, making
+    /// a single lazy pass over the directory
+    #[cfg(feature = "std")]
+    fn nodes_in_dir<'a, F>(dir: &Path, filter: F)
+        -> impl Iterator<Item = (PathBuf, fs::Metadata)> + 'a
+        where F: Fn(&OsStr) -> bool + 'a {
+        // Unreadable directories and entries are silently skipped
+        trace!(dir = %dir.display(), "scanning directory");
+        fs::read_dir(dir).into_iter().flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(move |entry| filter(&entry.file_name()))
+            .map(|entry| entry.path())
+            // lstat so symlinks aren't followed
+            .filter_map(|path| match retry_interrupted(|| fs::symlink_metadata(&path)) {
+                Ok(m) => {
+                    trace!(path = %path.display(), rdev = m.rdev(), "checked device node");
+                    Some((path, m))
+                },
+                Err(_) => None
+            })
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    mod tests {
+        use super::*;
+        // Only needed by the tests, so that the library itself just links libc
+        extern crate nix;
+        use self::nix::sys::wait::{waitpid, WaitStatus};
+        use self::nix::unistd::{fork, setsid, ForkResult};
+        use ::{has_ctty, try_get_ctty_dev};
+
+        /// Runs f in a forked child that has been moved into a new session, and
+        /// therefore has no controlling tty, returning its result
+        fn in_new_session<F: FnOnce() -> bool>(f: F) -> bool {
+            match unsafe { fork() }.unwrap() {
+                ForkResult::Child => {
+                    let ok = setsid().is_ok() && f();
+                    std::process::exit(if ok { 0 } else { 1 });
+                },
+                ForkResult::Parent { child } => {
+                    waitpid(child, None).unwrap() == WaitStatus::Exited(child, 0)
+                }
+            }
+        }
+
+        fn stat_with_tty_nr(tty_nr: &str) -> String {
+            format!("1234 (cat) R 1 1234 1234 {} 1234 4194304 0 0 0 0", tty_nr)
+        }
+
+        // Inverse of decode_tty_nr, mirroring the kernel's new_encode_dev
+        fn encode_tty_nr(dev: Dev) -> u32 {
+            let (maj, min) = (major(dev), minor(dev));
+            (min & 0xff) | (maj << 8) | ((min & !0xff) << 12)
+        }
+
+        #[test]
+        fn test_parse_large_tty_nr() {
+            // A tty_nr with bit 31 set is printed by the kernel as a negative int
+            let dev = parse_ctty_dev_from_stat(&stat_with_tty_nr("-2147450880")).unwrap();
+            assert_eq!(dev, decode_tty_nr(0x8000_8000));
+        }
+
+        #[test]
+        fn test_decode_tty_nr_large_minor() {
+            for &(maj, min) in &[(4, 1), (136, 255), (136, 256), (136, 300), (143, 0xfffff)] {
+                let dev = decode_tty_nr(encode_tty_nr(makedev(maj, min)));
+                assert_eq!(dev, makedev(maj, min));
+                assert_eq!((u64::from(major(dev)), u64::from(minor(dev))), (maj, min));
+            }
+        }
+
+        #[test]
+        fn test_classify_dev() {
+            assert_eq!(classify_dev(makedev(4, 2)), CttyKind::VirtualConsole(2));
+            assert_eq!(classify_dev(makedev(4, 63)), CttyKind::VirtualConsole(63));
+            assert_eq!(classify_dev(makedev(4, 64)), CttyKind::SerialConsole);
+            assert_eq!(classify_dev(makedev(188, 0)), CttyKind::SerialConsole);
+            assert_eq!(classify_dev(makedev(136, 3)), CttyKind::Pseudo(3));
+            assert_eq!(classify_dev(makedev(136, 300)), CttyKind::Pseudo(300));
+            assert_eq!(classify_dev(makedev(137, 4)), CttyKind::Pseudo(260));
+            assert_eq!(classify_dev(makedev(5, 1)), CttyKind::Other);
+        }
+
+        #[test]
+        fn test_parse_pts_major() {
+            let devices = "Character devices:\n  1 mem\n  4 tty\n128 ptm\n232 pts\n\n\
+                           Block devices:\n136 pts\n259 blkext\n";
+            assert_eq!(parse_pts_major(devices), Some(232));
+
+            // Older kernels list every major the driver spans
+            let spanned = "Character devices:\n143 pts\n136 pts\n137 pts\n";
+            assert_eq!(parse_pts_major(spanned), Some(136));
+
+            assert_eq!(parse_pts_major("Character devices:\n  1 mem\n\nBlock devices:\n136 pts\n"), None);
+            assert_eq!(parse_pts_major(""), None);
+        }
+
+        #[test]
+        fn test_get_raw_tty_nr() -> Result<(), CttyError> {
+            assert_eq!(get_ctty_dev()?, decode_tty_nr(get_raw_tty_nr()?));
+            Ok(())
+        }
+
+        #[test]
+        fn test_lookup_survives_signals() -> Result<(), Box<dyn std::error::Error>> {
+            use std::sync::atomic::{AtomicBool, Ordering};
+            use std::sync::Arc;
+            use std::thread;
+
+            use self::libc::c_int;
+
+            extern "C" fn ignore(_: c_int) {}
+
+            // Without SA_RESTART, interrupted syscalls fail with EINTR instead of resuming
+            unsafe {
+                let mut sa: self::libc::sigaction = std::mem::zeroed();
+                sa.sa_sigaction = ignore as extern "C" fn(c_int) as usize;
+                self::libc::sigemptyset(&mut sa.sa_mask);
+                assert_eq!(self::libc::sigaction(self::libc::SIGUSR1, &sa, std::ptr::null_mut()), 0);
+            }
+
+            // pthread_t is a pointer on musl, which isn't Send
+            struct Target(self::libc::pthread_t);
+            unsafe impl Send for Target {}
+
+            let target = Target(unsafe { self::libc::pthread_self() });
+            let done = Arc::new(AtomicBool::new(false));
+            let signaller = {
+                let done = done.clone();
+                thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        unsafe { self::libc::pthread_kill(target.0, self::libc::SIGUSR1) };
+                        thread::yield_now();
+                    }
+                })
+            };
+
+            let expected = get_ctty_dev()?;
+            let res = (0..200).map(|_| {
+                let dev = get_ctty_dev()?;
+                ::get_path_for_dev(dev)?;
+                Ok(dev)
+            }).collect::<Result<Vec<Dev>, CttyError>>();
+            done.store(true, Ordering::Relaxed);
+            signaller.join().unwrap();
+
+            assert!(res?.iter().all(|&dev| dev == expected));
+            Ok(())
+        }
+
+        #[test]
+        fn test_parse_non_ascii_comm() {
+            for comm in &["ça va", "日本", "é)ü", "🦀 )"] {
+                let stat = format!("1234 ({}) R 1 1234 1234 34817 1234 4194304 0 0", comm);
+                let parsed = parse_proc_stat(&stat).unwrap();
+                assert_eq!(parsed.comm, *comm);
+                assert_eq!(parse_ctty_dev_from_stat(&stat), Ok(makedev(136, 1)));
+            }
+
+            // Multi-byte characters right after the ')' are an error, not a panic
+            let stat = "1234 (cat)é R 1 1234 1234 34817 1234";
+            assert!(matches!(parse_ctty_dev_from_stat(stat), Err(CttyError::SystemDataParseFailure)));
+        }
+
+        #[test]
+        fn test_parse_unclosed_comm() {
+            // Found by the parse_stat fuzz target: a leading '(' with no ')' after it
+            for stat in &["(", "(cat R 1 1234 1234 34817 1234"] {
+                assert!(matches!(parse_ctty_dev_from_stat(stat), Err(CttyError::SystemDataParseFailure)));
+            }
+        }
+
+        #[test]
+        fn test_parse_empty_stat() {
+            for stat in &["", "\n", "   "] {
+                assert!(matches!(parse_proc_stat(stat), Err(CttyError::SystemDataParseFailure)));
+                assert!(matches!(get_ctty_dev_from_reader(stat.as_bytes()),
+                                 Err(CttyError::SystemDataParseFailure)));
+            }
+        }
+
+        #[test]
+        fn test_get_ctty_dev_from_chunked_reader() -> Result<(), CttyError> {
+            // Hands out a byte per read, as if each one were a separate syscall
+            struct Trickle<'a>(&'a [u8]);
+            impl<'a> Read for Trickle<'a> {
+                fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                    match (self.0.split_first(), buf.first_mut()) {
+                        (Some((&b, rest)), Some(out)) => {
+                            *out = b;
+                            self.0 = rest;
+                            Ok(1)
+                        },
+                        _ => Ok(0)
+                    }
+                }
+            }
+
+            let stat = format!("1234 ({}) R 1 1234 1234 34817 1234 4194304", "a) (b".repeat(200));
+            assert_eq!(get_ctty_dev_from_reader(Trickle(stat.as_bytes()))?, makedev(136, 1));
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_ctty_dev_from_reader() -> Result<(), CttyError> {
+            let stat = stat_with_tty_nr("34817");
+            assert_eq!(get_ctty_dev_from_reader(stat.as_bytes())?, makedev(136, 1));
+
+            let proc_stat = File::open("/proc/self/stat").map_err(CttyError::IOError)?;
+            assert_eq!(get_ctty_dev_from_reader(proc_stat)?, get_ctty_dev()?);
+
+            let truncated = &stat[..stat.find(") R").unwrap() + 5];
+            let bad_inputs: [&[u8]; 5] = [b"", truncated.as_bytes(), b"garbage", b"\xff\xfe(",
+                                          b"1234 (cat) R 1 1234 1234 pts 1234"];
+            for input in bad_inputs.iter() {
+                assert!(matches!(get_ctty_dev_from_reader(*input), Err(CttyError::SystemDataParseFailure)),
+                        "{:?}", input);
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_ctty_dev_thread() -> Result<(), CttyError> {
+            let dev = get_ctty_dev()?;
+            assert_eq!(get_ctty_dev_thread()?, dev);
+            // Other threads are in the same session, so they see the same ctty
+            let from_thread = std::thread::spawn(get_ctty_dev_thread).join().unwrap();
+            assert_eq!(from_thread?, dev);
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_ctty_dev_for_missing_pid() {
+            let res = get_ctty_dev_for_pid(u32::MAX);
+            assert!(matches!(res, Err(CttyError::NotFound)));
+        }
+
+        #[test]
+        fn test_get_ctty_dev_for_restricted_pid() -> Result<(), CttyError> {
+            use std::fs::{self, Permissions};
+            use std::os::unix::fs::PermissionsExt;
+            use self::nix::unistd::{setuid, Uid};
+
+            // Stand in for a hidepid=2 /proc/<pid> directory that the caller can't search
+            let dir = std::env::temp_dir().join(format!("ctty-hidepid-{}", std::process::id()));
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join("stat"), stat_with_tty_nr("34816"))?;
+            fs::set_permissions(&dir, Permissions::from_mode(0o000))?;
+
+            // Root isn't subject to permission checks, so drop to nobody first
+            let denied = in_new_session(|| {
+                (!Uid::effective().is_root() || setuid(Uid::from_raw(65534)).is_ok())
+                    && matches!(ctty_dev_from_pid_stat(&dir.join("stat")),
+                                Err(CttyError::SystemPermissionFailure(self::libc::EACCES)))
+            });
+            fs::set_permissions(&dir, Permissions::from_mode(0o700))?;
+            let gone = ctty_dev_from_pid_stat(&dir.join("missing"));
+            fs::remove_dir_all(&dir)?;
+
+            assert!(denied);
+            assert!(matches!(gone, Err(CttyError::NotFound)));
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_path_for_dev_in_unreadable() -> Result<(), CttyError> {
+            use std::fs::{self, Permissions};
+            use std::os::unix::fs::PermissionsExt;
+            use self::nix::sys::stat::{mknod, Mode, SFlag};
+            use self::nix::unistd::{setuid, Uid};
+
+            let root = std::env::temp_dir().join(format!("ctty-unreadable-{}", std::process::id()));
+            fs::create_dir_all(root.join("pts"))?;
+            let serial = makedev(4, 79);
+            let made = mknod(&root.join("ttyS79"), SFlag::S_IFCHR, Mode::S_IRUSR, serial.0).is_ok();
+            // Root isn't subject to permission checks, so each lookup drops to nobody first
+            let as_nobody = |f: &dyn Fn() -> bool| in_new_session(|| {
+                (!Uid::effective().is_root() || setuid(Uid::from_raw(65534)).is_ok()) && f()
+            });
+
+            // An unreadable pts doesn't stop the rest of the directory being searched
+            fs::set_permissions(root.join("pts"), Permissions::from_mode(0o000))?;
+            let skipped = as_nobody(&|| get_path_for_dev_in(serial, &root) == Ok(root.join("ttyS79")));
+            // But if the root itself can't be listed, nothing was searched
+            fs::set_permissions(&root, Permissions::from_mode(0o000))?;
+            let denied = as_nobody(&|| {
+                get_path_for_dev_in(serial, &root) == Err(CttyError::SystemPermissionFailure(self::libc::EACCES))
+            });
+            fs::set_permissions(&root, Permissions::from_mode(0o700))?;
+            fs::set_permissions(root.join("pts"), Permissions::from_mode(0o700))?;
+            fs::remove_dir_all(&root)?;
+
+            // Creating device nodes needs CAP_MKNOD, so there's nothing to check without it
+            if made {
+                assert!(skipped);
+                assert!(denied);
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn test_is_not_found() {
+            assert!(is_not_found(&CttyError::IOError(io::Error::from_raw_os_error(self::libc::ENOENT))));
+            assert!(is_not_found(&CttyError::IOError(io::Error::from_raw_os_error(self::libc::ESRCH))));
+            assert!(!is_not_found(&CttyError::IOError(io::Error::from_raw_os_error(self::libc::EIO))));
+            assert!(!is_not_found(&CttyError::SystemPermissionFailure(self::libc::EACCES)));
+        }
+
+        #[test]
+        fn test_map_io_error() {
+            let denied = map_io_error(io::Error::from_raw_os_error(self::libc::EPERM));
+            assert!(matches!(denied, CttyError::SystemPermissionFailure(self::libc::EPERM)));
+
+            let kind_only = map_io_error(io::Error::from(io::ErrorKind::PermissionDenied));
+            assert!(matches!(kind_only, CttyError::SystemPermissionFailure(self::libc::EACCES)));
+
+            let other = map_io_error(io::Error::from(io::ErrorKind::InvalidData));
+            assert!(matches!(other, CttyError::IOError(_)));
+        }
+
+        #[test]
+        fn test_get_ctty_dev_for_pid_detached() {
+            assert_eq!(parse_ctty_dev_from_stat(&stat_with_tty_nr("0")), Err(CttyError::NotFound));
+            assert!(in_new_session(|| {
+                get_ctty_dev_for_pid(std::process::id()) == Err(CttyError::NotFound)
+                    && get_ctty_dev() == Err(CttyError::NotFound)
+            }));
+        }
+
+        #[test]
+        fn test_has_ctty_detached() {
+            assert!(in_new_session(|| !has_ctty()));
+        }
+
+        #[test]
+        fn test_same_ctty_detached() {
+            let parent = std::process::id();
+            assert!(in_new_session(|| {
+                // Neither sharing the parent's ctty nor having one to share with itself
+                let child = std::process::id();
+                ::same_ctty(parent, child) == Ok(false) && ::same_ctty(child, child) == Ok(false)
+            }));
+        }
+
+        #[test]
+        fn test_get_ctty_dev_for_session_non_leader() {
+            let parent = std::process::id() as i32;
+            assert!(in_new_session(|| {
+                // The new session has no ctty, and the parent isn't the leader of any
+                // session, so it's no sid
+                let leader = get_ctty_dev_for_session(std::process::id() as i32);
+                leader == Err(CttyError::NotFound) && get_ctty_dev_for_session(parent) == Err(CttyError::NotFound)
+            }));
+        }
+
+        #[test]
+        fn test_get_parent_ctty_dev_detached() {
+            assert!(in_new_session(|| {
+                // The new session has no ctty, so neither does a grandchild's parent
+                match unsafe { fork() }.unwrap() {
+                    ForkResult::Child => {
+                        let ok = get_parent_ctty_dev() == Err(CttyError::NotFound);
+                        std::process::exit(if ok { 0 } else { 1 });
+                    },
+                    ForkResult::Parent { child } => {
+                        waitpid(child, None).unwrap() == WaitStatus::Exited(child, 0)
+                    }
+                }
+            }));
+        }
+
+        #[test]
+        fn test_try_get_ctty_dev_detached() {
+            assert!(in_new_session(|| matches!(try_get_ctty_dev(), Ok(None))));
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn test_get_path_for_console_devs() {
+            use self::nix::sys::stat::stat;
+
+            // Not every machine has these, so only check the ones that are present
+            for path in &["/dev/tty", "/dev/tty1", "/dev/ttyS0"] {
+                if let Ok(s) = stat(*path) {
+                    assert_eq!(get_path_for_dev_buf(Dev(s.st_rdev)).unwrap(), Path::new(path));
+                }
+            }
+        }
+
+        #[test]
+        fn test_parse_comm_with_spaces_and_parens() {
+            for comm in &["(tmux: server)", "(a (b) c)", "(  )", "(()", "(weird )name)"] {
+                let stat = format!("1234 {} S 1 1234 1234 34816 1234 4194304 0 0", comm);
+                assert_eq!(parse_ctty_dev_from_stat(&stat).unwrap(), makedev(136, 0));
+            }
+        }
+
+        #[test]
+        fn test_parse_proc_stat() {
+            let stat = "4321 (my (weird) prog) T 1 4000 3999 34817 -1 4194304 0 0";
+            assert_eq!(parse_proc_stat(stat).unwrap(), ProcStat {
+                pid: 4321,
+                comm: String::from("my (weird) prog"),
+                state: 'T',
+                ppid: 1,
+                pgrp: 4000,
+                session: 3999,
+                tty_nr: 34817,
+                tpgid: -1
+            });
+        }
+
+        #[test]
+        fn test_in_foreground() {
+            let with = |tty_nr: &str, tpgid: &str| {
+                let stat = format!("1234 (cat) R 1 4000 3999 {} {} 4194304", tty_nr, tpgid);
+                in_foreground(&parse_proc_stat(&stat).unwrap())
+            };
+            assert_eq!(with("34817", "4000"), Ok(true));
+            assert_eq!(with("34817", "4100"), Ok(false));
+            assert_eq!(with("0", "-1"), Err(CttyError::NotFound));
+            assert_eq!(with("34817", "-1"), Err(CttyError::NotFound));
+        }
+
+        #[test]
+        fn test_is_foreground() -> Result<(), CttyError> {
+            // The test harness is run from the terminal by a shell, in whichever
+            // process group it's given, so just agree with the terminal itself
+            let pgrp = unsafe { self::libc::getpgrp() };
+            assert_eq!(is_foreground()?, ::get_foreground_pgrp()? == pgrp);
+            assert!(in_new_session(|| is_foreground() == Err(CttyError::NotFound)));
+            Ok(())
+        }
+
+        #[test]
+        fn test_parse_proc_stat_truncated() {
+            for stat in &["", "1234", "1234 (cat", "1234 (cat) R 1 1234", ")1234 (cat"] {
+                assert!(matches!(parse_proc_stat(stat), Err(CttyError::SystemDataParseFailure)));
+            }
+        }
+
+        #[test]
+        fn test_parse_extra_space_after_comm() {
+            let stat = "1234 (weird )name)  S 1 1234 1234 34816 1234 4194304 0 0";
+            assert_eq!(parse_ctty_dev_from_stat(stat).unwrap(), makedev(136, 0));
+        }
+
+        #[test]
+        fn test_get_ctty_dev_from_proc() -> Result<(), CttyError> {
+            use std::fs;
+
+            let proc_root = std::env::temp_dir().join(format!("ctty-proc-{}", std::process::id()));
+            fs::create_dir_all(proc_root.join("self"))?;
+            fs::write(proc_root.join("self/stat"), stat_with_tty_nr("1025"))?;
+
+            let res = get_ctty_dev_from_proc(&proc_root);
+            fs::remove_dir_all(&proc_root)?;
+            assert_eq!(res?, makedev(4, 1));
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_ctty_dev_without_proc() -> Result<(), CttyError> {
+            let missing = get_ctty_dev_or_fallback(Path::new("/nonexistent/self/stat"))?;
+            assert_eq!(missing, get_ctty_dev()?);
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_ctty_dev_from_dev_tty_detached() {
+            assert!(in_new_session(|| {
+                matches!(get_ctty_dev_from_dev_tty(), Err(CttyError::NotFound))
+            }));
+        }
+
+        #[test]
+        fn test_devs_in_dir_returns_every_match() -> Result<(), CttyError> {
+            use std::fs;
+            use self::nix::sys::stat::{mknod, Mode, SFlag};
+
+            let dir = std::env::temp_dir().join(format!("ctty-dev-{}", std::process::id()));
+            fs::create_dir_all(&dir)?;
+            let dev = makedev(136, 42);
+            let mut made = true;
+            for name in &["tty-a", "tty-b"] {
+                made &= mknod(&dir.join(name), SFlag::S_IFCHR, Mode::S_IRUSR, dev.0).is_ok();
+            }
+            fs::write(dir.join("not-a-tty"), "")?;
+
+            let mut paths: Vec<PathBuf> = devs_in_dir(dev, &dir, |_| true).collect();
+            fs::remove_dir_all(&dir)?;
+
+            // Creating device nodes needs CAP_MKNOD, so there's nothing to check without it
+            if made {
+                paths.sort();
+                assert_eq!(paths, vec![dir.join("tty-a"), dir.join("tty-b")]);
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn test_devs_in_dir_skips_block_devices() -> Result<(), CttyError> {
+            use std::fs;
+            use self::nix::sys::stat::{mknod, Mode, SFlag};
+
+            let dir = std::env::temp_dir().join(format!("ctty-blk-{}", std::process::id()));
+            fs::create_dir_all(&dir)?;
+            let dev = makedev(136, 43);
+            let made = mknod(&dir.join("tty-blk"), SFlag::S_IFBLK, Mode::S_IRUSR, dev.0).is_ok()
+                && mknod(&dir.join("tty-chr"), SFlag::S_IFCHR, Mode::S_IRUSR, dev.0).is_ok();
+
+            let paths: Vec<PathBuf> = devs_in_dir(dev, &dir, |_| true).collect();
+            fs::remove_dir_all(&dir)?;
+
+            // Creating device nodes needs CAP_MKNOD, so there's nothing to check without it
+            if made {
+                assert_eq!(paths, vec![dir.join("tty-chr")]);
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_path_for_dev_in_symlink() -> Result<(), CttyError> {
+            use std::fs;
+            use std::os::unix::fs::symlink;
+            use self::nix::sys::stat::{mknod, Mode, SFlag};
+
+            let root = std::env::temp_dir().join(format!("ctty-link-{}", std::process::id()));
+            fs::create_dir_all(root.join("elsewhere"))?;
+            // Resolved paths are canonical, so start from a canonical root to compare them
+            let root = fs::canonicalize(&root)?;
+            let elsewhere = root.join("elsewhere");
+            let (console, tty) = (makedev(5, 1), makedev(4, 75));
+            let made = mknod(&elsewhere.join("console"), SFlag::S_IFCHR, Mode::S_IRUSR, console.0).is_ok()
+                && mknod(&root.join("tty75"), SFlag::S_IFCHR, Mode::S_IRUSR, tty.0).is_ok();
+            // One link leads out of the scanned directories, the other to a node in them
+            symlink(elsewhere.join("console"), root.join("console"))?;
+            symlink(root.join("tty75"), root.join("ttylink"))?;
+
+            let console_path = get_path_for_dev_in(console, &root);
+            let tty_paths: Vec<PathBuf> = matching_devs(tty, &root).collect();
+            fs::remove_dir_all(&root)?;
+
+            // Creating device nodes needs CAP_MKNOD, so there's nothing to check without it
+            if made {
+                assert_eq!(console_path?, elsewhere.join("console"));
+                // The node and the link to it are only reported once
+                assert_eq!(tty_paths, vec![root.join("tty75")]);
+            }
+            Ok(())
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn test_scan_devices() -> Result<(), CttyError> {
+            use std::fs;
+            use std::os::unix::fs::symlink;
+            use self::nix::sys::stat::{mknod, Mode, SFlag};
+
+            let root = std::env::temp_dir().join(format!("ctty-scan-{}", std::process::id()));
+            fs::create_dir_all(root.join("pts"))?;
+            let root = fs::canonicalize(&root)?;
+            let (pts, serial) = (makedev(136, 78), makedev(4, 78));
+            let made = mknod(&root.join("pts/78"), SFlag::S_IFCHR, Mode::S_IRUSR, pts.0).is_ok()
+                && mknod(&root.join("ttyS78"), SFlag::S_IFCHR, Mode::S_IRUSR, serial.0).is_ok()
+                && mknod(&root.join("sda"), SFlag::S_IFBLK, Mode::S_IRUSR, serial.0).is_ok();
+            symlink(root.join("ttyS78"), root.join("console"))?;
+
+            let nodes: Vec<(PathBuf, u64)> = scan_devices(&root).into_iter()
+                .map(|(path, m)| (path, m.rdev()))
+                .collect();
+            let lookups = (get_path_for_dev_in(pts, &root), get_path_for_dev_in(serial, &root));
+            fs::remove_dir_all(&root)?;
+
+            if made {
+                // sda isn't a terminal name, and console is the same node as ttyS78
+                assert_eq!(nodes, vec![(root.join("pts/78"), pts.0), (root.join("ttyS78"), serial.0)]);
+                // The lookups are filters over the same walk
+                assert_eq!(lookups.0?, nodes[0].0);
+                assert_eq!(lookups.1?, nodes[1].0);
+            }
+            Ok(())
+        }
+
+        #[test]
+        #[cfg(target_os = "linux")]
+        fn test_devs_in_mounts() -> Result<(), CttyError> {
+            use std::fs;
+            use self::nix::sys::stat::{mknod, Mode, SFlag};
+
+            // A device tree relocated into a directory with a space in its name
+            let root = std::env::temp_dir().join(format!("ctty mounts-{}", std::process::id()));
+            fs::create_dir_all(root.join("pts"))?;
+            let (pts, serial) = (makedev(136, 77), makedev(4, 76));
+            let made = mknod(&root.join("pts/77"), SFlag::S_IFCHR, Mode::S_IRUSR, pts.0).is_ok()
+                && mknod(&root.join("ttyS12"), SFlag::S_IFCHR, Mode::S_IRUSR, serial.0).is_ok();
+
+            let escaped = root.to_str().unwrap().replace(' ', "\\040");
+            let mounts = format!("proc /proc proc rw 0 0\n\
+                                  devpts /dev/pts devpts rw 0 0\n\
+                                  devpts {0}/pts devpts rw,nosuid 0 0\n\
+                                  udev {0} devtmpfs rw 0 0\n", escaped);
+            let found_pts: Vec<PathBuf> = devs_in_mounts(pts, &mounts).collect();
+            let found_serial: Vec<PathBuf> = devs_in_mounts(serial, &mounts).collect();
+            let missing = devs_in_mounts(makedev(136, 78), &mounts).next();
+            fs::remove_dir_all(&root)?;
+
+            // Creating device nodes needs CAP_MKNOD, so there's nothing to check without it
+            if made {
+                assert_eq!(found_pts, vec![root.join("pts/77")]);
+                assert_eq!(found_serial, vec![root.join("ttyS12")]);
+                assert_eq!(missing, None);
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn test_devpts_not_mounted() -> Result<(), CttyError> {
+            use std::fs;
+
+            // A /dev without devpts: first with no pts directory, then an empty one
+            let root = std::env::temp_dir().join(format!("ctty-nopts-{}", std::process::id()));
+            fs::create_dir_all(&root)?;
+            let (pts, serial) = (makedev(136, 998), makedev(4, 998));
+            let absent = (get_path_for_dev_in(pts, &root), get_path_for_dev_in(serial, &root));
+            fs::create_dir(root.join("pts"))?;
+            let empty = get_path_for_dev_in(pts, &root);
+            fs::remove_dir_all(&root)?;
+
+            assert!(matches!(absent.0, Err(CttyError::DevptsNotMounted)));
+            // Only pseudo ttys are expected under pts
+            assert!(matches!(absent.1, Err(CttyError::NotFound)));
+            assert!(matches!(empty, Err(CttyError::DevptsNotMounted)));
+            Ok(())
+        }
+
+        #[test]
+        fn test_device_not_in_dev() -> Result<(), CttyError> {
+            use std::fs;
+
+            // A /dev with none of the host's terminals, as a mount namespace might have
+            let root = std::env::temp_dir().join(format!("ctty-nsdev-{}", std::process::id()));
+            fs::create_dir_all(root.join("pts"))?;
+            // Its devpts is mounted, it just isn't the host's
+            fs::write(root.join("pts/ptmx"), b"")?;
+            let ctty = get_path_for_dev_in(get_ctty_dev()?, &root);
+            let other = get_path_for_dev_in(makedev(136, 999), &root);
+            fs::remove_dir_all(&root)?;
+
+            assert!(matches!(ctty, Err(CttyError::DeviceNotInDev)));
+            assert!(matches!(other, Err(CttyError::NotFound)));
+            Ok(())
+        }
+
+        #[test]
+        #[cfg(target_os = "linux")]
+        fn test_unescape_mount_path() {
+            assert_eq!(unescape_mount_path("/mnt/dev\\040tree"), Path::new("/mnt/dev tree"));
+            assert_eq!(unescape_mount_path("/a\\134b"), Path::new("/a\\b"));
+            assert_eq!(unescape_mount_path("/trailing\\04"), Path::new("/trailing\\04"));
+        }
+
+        #[test]
+        fn test_get_path_for_dev_in_non_utf8() -> Result<(), CttyError> {
+            use std::fs;
+            use std::os::unix::ffi::OsStrExt;
+            use self::nix::sys::stat::{mknod, Mode, SFlag};
+
+            let root = std::env::temp_dir().join(format!("ctty-utf8-{}", std::process::id()));
+            fs::create_dir_all(&root)?;
+            let node = root.join(OsStr::from_bytes(b"tty\xff"));
+            let dev = makedev(4, 74);
+            let made = mknod(&node, SFlag::S_IFCHR, Mode::S_IRUSR, dev.0).is_ok();
+
+            let path = get_path_for_dev_in(dev, &root);
+            fs::remove_dir_all(&root)?;
+
+            // Creating device nodes needs CAP_MKNOD, so there's nothing to check without it
+            if made {
+                let path = path?;
+                assert_eq!(path.as_os_str(), node.as_os_str());
+                assert!(path.to_string_lossy().ends_with("tty\u{fffd}"));
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_path_for_dev_in() -> Result<(), CttyError> {
+            use std::fs;
+            use self::nix::sys::stat::{mknod, Mode, SFlag};
+
+            let root = std::env::temp_dir().join(format!("ctty-root-{}", std::process::id()));
+            fs::create_dir_all(root.join("pts"))?;
+            let nodes = [("pts/5", makedev(136, 5)), ("ttyS9", makedev(4, 73)), ("sda", makedev(8, 0))];
+            let mut made = true;
+            for &(name, dev) in &nodes {
+                made &= mknod(&root.join(name), SFlag::S_IFCHR, Mode::S_IRUSR, dev.0).is_ok();
+            }
+
+            let pts = get_path_for_dev_in(makedev(136, 5), &root);
+            let serial = get_path_for_dev_in(makedev(4, 73), &root);
+            let disk = get_path_for_dev_in(makedev(8, 0), &root);
+            fs::remove_dir_all(&root)?;
+
+            // Creating device nodes needs CAP_MKNOD, so there's nothing to check without it
+            if made {
+                assert_eq!(pts?, root.join("pts/5"));
+                assert_eq!(serial?, root.join("ttyS9"));
+                // Only tty* nodes are considered outside of pts
+                assert_eq!(disk, Err(CttyError::NotFound));
+            }
+            Ok(())
+        }
+
+        #[cfg(feature = "tokio")]
+        #[test]
+        fn test_async_matches_sync() -> Result<(), CttyError> {
+            let rt = super::tokio::runtime::Builder::new_current_thread().build()?;
+            let dev = rt.block_on(get_ctty_dev_async())?;
+            assert_eq!(dev, get_ctty_dev()?);
+            assert_eq!(rt.block_on(get_path_for_dev_async(dev))?, ::get_path_for_dev(dev)?);
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_paths_for_dev() -> Result<(), CttyError> {
+            let dev = get_ctty_dev()?;
+            let paths = get_paths_for_dev(dev)?;
+            assert_eq!(paths[0], get_path_for_dev_buf(dev)?);
+            assert!(matches!(get_paths_for_dev(makedev(0xfff, 0xfffff)), Err(CttyError::NotFound)));
+            Ok(())
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn test_list_terminals() -> Result<(), CttyError> {
+            let dev = get_ctty_dev()?;
+            let terminals = list_terminals()?;
+            assert!(terminals.contains(&(get_path_for_dev_buf(dev)?, dev)));
+            // /dev/null is a character device, but not a terminal
+            assert!(terminals.iter().all(|(path, _)| path != Path::new("/dev/null")));
+            Ok(())
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn test_sort_by_dev() -> Result<(), CttyError> {
+            let mut terminals = vec![
+                (PathBuf::from("/dev/pts/10"), makedev(136, 10)),
+                (PathBuf::from("/dev/pts/256"), makedev(136, 256)),
+                (PathBuf::from("/dev/ttyS0"), makedev(4, 64)),
+                (PathBuf::from("/dev/pts/1024"), makedev(137, 0)),
+                (PathBuf::from("/dev/pts/2"), makedev(136, 2)),
+            ];
+            sort_by_dev(&mut terminals);
+            let paths: Vec<&str> = terminals.iter().map(|(p, _)| p.to_str().unwrap()).collect();
+            // By minor rather than by name, and pts/256 before major 137 even though its
+            // raw dev_t is larger
+            assert_eq!(paths, ["/dev/ttyS0", "/dev/pts/2", "/dev/pts/10", "/dev/pts/256", "/dev/pts/1024"]);
+
+            let sorted = list_terminals_sorted()?;
+            assert!(sorted.windows(2).all(|w| (major(w[0].1), minor(w[0].1)) <= (major(w[1].1), minor(w[1].1))));
+            assert_eq!(sorted.len(), list_terminals()?.len());
+            Ok(())
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn test_is_wsl_release() {
+            assert!(is_wsl_release("5.15.90.1-microsoft-standard-WSL2"));
+            assert!(is_wsl_release("4.4.0-19041-Microsoft"));
+            assert!(!is_wsl_release("6.1.0-18-amd64"));
+        }
+
+        // Smoke test for static builds, e.g. cargo test --target x86_64-unknown-linux-musl
+        #[cfg(target_env = "musl")]
+        #[test]
+        fn test_musl_get_ctty_dev() -> Result<(), CttyError> {
+            let dev = get_ctty_dev()?;
+            assert_eq!(get_path_for_dev_buf(dev)?, ::get_ctty_path_with_fallbacks()?);
+            Ok(())
+        }
+
+        #[test]
+        fn test_major_minor() {
+            let dev = makedev(136, 3);
+            assert_eq!((major(dev), minor(dev)), (136, 3));
+            assert_eq!(dev.to_string(), "136:3");
+        }
+
+        #[test]
+        fn test_is_pty() {
+            assert!(is_pty(makedev(136, 3)));
+            assert!(is_pty(makedev(143, 1000)));
+            assert!(!is_pty(makedev(4, 1)));
+            assert!(!is_pty(makedev(4, 64)));
+        }
+
+        #[test]
+        fn test_parse_tty_nr_round_trip() -> Result<(), CttyError> {
+            let dev = get_ctty_dev()?;
+            let tty_nr = encode_tty_nr(dev) as i32;
+            let parsed = parse_ctty_dev_from_stat(&stat_with_tty_nr(&tty_nr.to_string()))?;
+            assert_eq!(parsed, dev);
+            get_path_for_dev_buf(parsed)?;
+            Ok(())
+        }
+    }
+}
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use linux::*;
+
+// For the BSDs and macOS, the ctty is read from the process's kinfo_proc via sysctl.
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly", target_os = "macos", target_os = "netbsd",
+          target_os = "openbsd"))]
+mod bsd {
+    // Every unsafe block here is an FFI call or reads what one returned, so each one
+    // has to say why it's sound
+    #![deny(unsafe_op_in_unsafe_fn, clippy::undocumented_unsafe_blocks)]
+
+    use std::ffi::{CStr, OsString};
+    use std::io;
+    use std::mem;
+    use std::os::unix::ffi::OsStringExt;
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+
+    use ::{CttyError, Dev};
+
+    extern crate libc;
+    use self::libc::{S_IFCHR, mode_t, dev_t, c_char, c_int, c_uint, c_void, pid_t};
+    #[cfg(any(target_os = "dragonfly", target_os = "netbsd"))]
+    use self::libc::size_t;
+    use self::libc::{CTL_KERN, KERN_PROC_PID};
+
+    extern "C" {
+        // Provided by system libc
+        #[cfg(any(target_os = "freebsd", target_os = "macos"))]
+        fn devname_r(dev: dev_t, type_: mode_t, buf: *mut u8, len: c_int) -> *mut c_char;
+        #[cfg(target_os = "dragonfly")]
+        fn devname_r(dev: dev_t, type_: mode_t, buf: *mut u8, len: size_t) -> *mut c_char;
+        #[cfg(target_os = "netbsd")]
+        fn devname_r(dev: dev_t, type_: mode_t, buf: *mut c_char, len: size_t) -> c_int;
+        #[cfg(target_os = "openbsd")]
+        fn devname(dev: dev_t, type_: mode_t) -> *mut c_char;
+    }
+
+    // Only linked with the kvm feature. kvm_t is opaque, so it's only handled by pointer
+    #[cfg(all(target_os = "freebsd", feature = "kvm"))]
+    #[link(name = "kvm")]
+    extern "C" {
+        fn kvm_openfiles(execfile: *const c_char, corefile: *const c_char,
+                         swapfile: *const c_char, flags: c_int, errbuf: *mut c_char) -> *mut c_void;
+        fn kvm_getprocs(kd: *mut c_void, op: c_int, arg: c_int, cnt: *mut c_int) -> *mut kinfo_proc;
+        fn kvm_close(kd: *mut c_void) -> c_int;
+    }
+
+    /// Size of the buffer kvm_openfiles writes its error message to, _POSIX2_LINE_MAX
+    #[cfg(all(target_os = "freebsd", feature = "kvm"))]
+    const KVM_ERRBUF_LEN: usize = 2048;
+
+    // The per-process struct returned by sysctl, and the MIB used to request it.
+    // NetBSD and OpenBSD also take the struct size and number of entries to return
+    #[cfg(any(target_os = "freebsd", target_os = "dragonfly", target_os = "macos",
+              target_os = "openbsd"))]
+    use self::libc::KERN_PROC as KINFO_PROC_MIB;
+    #[cfg(any(all(target_os = "freebsd", not(feature = "bindgen")), target_os = "dragonfly",
+              target_os = "openbsd"))]
+    use self::libc::kinfo_proc;
+    #[cfg(target_os = "netbsd")]
+    use self::libc::{kinfo_proc2 as kinfo_proc, KERN_PROC2 as KINFO_PROC_MIB};
+    #[cfg(all(any(target_os = "freebsd", target_os = "macos"), feature = "bindgen"))]
+    use self::kinfo_proc_sys::kinfo_proc;
+    #[cfg(any(target_os = "freebsd", target_os = "dragonfly", target_os = "macos"))]
+    const KINFO_MIB_LEN: c_uint = 4;
+    #[cfg(any(target_os = "netbsd", target_os = "openbsd"))]
+    const KINFO_MIB_LEN: c_uint = 6;
+    #[cfg(any(target_os = "netbsd", target_os = "openbsd"))]
+    const NODEV: u32 = !0;
+
+    /// kinfo_proc generated from the system headers by build.rs, so its layout always
+    /// matches the OS being built for
+    #[cfg(all(any(target_os = "freebsd", target_os = "macos"), feature = "bindgen"))]
+    #[allow(non_camel_case_types, non_snake_case, non_upper_case_globals, dead_code,
+            clippy::undocumented_unsafe_blocks)]
+    mod kinfo_proc_sys {
+        include!(concat!(env!("OUT_DIR"), "/kinfo_proc.rs"));
+    }
+
+    /// macOS's struct kinfo_proc from sys/sysctl.h, which libc doesn't provide. Nested
+    /// structs that aren't needed are left opaque, only reserving the right space
+    #[cfg(all(target_os = "macos", not(feature = "bindgen")))]
+    #[repr(C)]
+    #[allow(non_camel_case_types)]
+    struct kinfo_proc {
+        kp_proc: [u64; 37], // struct extern_proc
+        kp_eproc: eproc
+    }
+
+    #[cfg(all(target_os = "macos", not(feature = "bindgen")))]
+    #[repr(C)]
+    #[allow(non_camel_case_types)]
+    struct eproc {
+        e_paddr: *mut c_void,
+        e_sess: *mut c_void,
+        e_pcred: [u64; 13], // struct _pcred
+        e_ucred: self::libc::xucred,
+        e_vm: [u64; 8], // struct vmspace
+        e_ppid: pid_t,
+        e_pgid: pid_t,
+        e_jobc: self::libc::c_short,
+        e_tdev: dev_t,
+        e_tpgid: pid_t,
+        e_tsess: *mut c_void,
+        e_wmesg: [c_char; 8],
+        e_xsize: i32,
+        e_xrssize: self::libc::c_short,
+        e_xccount: self::libc::c_short,
+        e_xswrss: self::libc::c_short,
+        e_flag: i32,
+        e_login: [c_char; 12],
+        e_spare: [i32; 4]
+    }
+
+    // sysctl refuses to fill a buffer that doesn't match the kernel's struct size
+    #[cfg(all(target_os = "macos", target_pointer_width = "64", not(feature = "bindgen")))]
+    const _: () = assert!(mem::size_of::<kinfo_proc>() == 648);
+
+    /// Returns the controlling tty's device from a process's kinfo_proc, or 0 if it
+    /// doesn't have one
+    #[cfg(target_os = "freebsd")]
+    #[allow(clippy::useless_conversion)] // ki_tdev is only 32 bits wide before FreeBSD 12
+    fn kinfo_ctty(kp: &kinfo_proc) -> u64 {
+        // ki_tdev is NODEV ((dev_t)-1) rather than 0 when there is no ctty. The !0 takes
+        // on ki_tdev's own width, so this holds for 32 and 64-bit dev_t alike
+        if kp.ki_tdev == !0 {
+            return 0;
+        }
+        u64::from(kp.ki_tdev)
+    }
+
+    /// Returns the controlling tty's device from a process's kinfo_proc, or 0 if it
+    /// doesn't have one
+    #[cfg(target_os = "dragonfly")]
+    fn kinfo_ctty(kp: &kinfo_proc) -> u64 {
+        // Set to NODEV when there is no ctty, as on FreeBSD
+        if kp.kp_tdev == !0 {
+            return 0;
+        }
+        kp.kp_tdev as u64
+    }
+
+    /// Returns the controlling tty's device from a process's kinfo_proc, or 0 if it
+    /// doesn't have one
+    #[cfg(target_os = "macos")]
+    fn kinfo_ctty(kp: &kinfo_proc) -> u64 {
+        // e_tdev is a signed dev_t, set to NODEV (-1) when there is no ctty
+        if kp.kp_eproc.e_tdev == !0 {
+            return 0;
+        }
+        kp.kp_eproc.e_tdev as u64
+    }
+
+    /// Returns the controlling tty's device from a process's kinfo_proc, or 0 if it
+    /// doesn't have one
+    #[cfg(any(target_os = "netbsd", target_os = "openbsd"))]
+    fn kinfo_ctty(kp: &kinfo_proc) -> u64 {
+        // p_tdev is a 32-bit field set to NODEV when there is no ctty
+        match kp.p_tdev {
+            NODEV => 0,
+            tdev => u64::from(tdev)
+        }
+    }
+
+    /// Returns the dev_t corresponding to the current process's controlling tty
+    pub fn get_ctty_dev() -> Result<Dev, CttyError> {
+        get_ctty_dev_for_pid(std::process::id())
+    }
+
+    /// Returns the dev_t corresponding to the controlling tty of the process with the given pid
+    ///
+    /// Fails with `CttyError::NotFound` if the process has no ctty or doesn't exist
+    /// (including one that exited during the lookup), and with
+    /// `CttyError::SystemPermissionFailure` if the system won't reveal it to the caller.
+    ///
+    /// With the kvm feature on FreeBSD, libkvm is asked first, and the sysctl is only
+    /// used if that fails.
+    pub fn get_ctty_dev_for_pid(pid: u32) -> Result<Dev, CttyError> {
+        #[cfg(all(target_os = "freebsd", feature = "kvm"))]
+        {
+            if let Some(tdev) = kvm_ctty_dev_for_pid(pid) {
+                return if tdev == 0 { Err(CttyError::NotFound) } else { Ok(Dev(tdev)) };
+            }
+        }
+        sysctl_ctty_dev_for_pid(pid)
+    }
+
+    /// Reads the ctty of the process with the given pid from its kinfo_proc, via the
+    /// KERN_PROC sysctl
+    fn sysctl_ctty_dev_for_pid(pid: u32) -> Result<Dev, CttyError> {
+        // SAFETY: kinfo_proc is a plain C struct, for which all zeroes is a valid value
+        let mut kp: kinfo_proc = unsafe { mem::zeroed() };
+        let mut size = mem::size_of::<kinfo_proc>();
+        // The MIB comes straight from libc's constants rather than sysctlnametomib, so
+        // each lookup is a single sysctl call with no name resolution to cache
+        let mut mib: [c_int; 6] = [CTL_KERN, KINFO_PROC_MIB, KERN_PROC_PID, pid as pid_t,
+                                   size as c_int, 1];
+
+        // Run sysctl, retrying if a signal interrupts it
+        let res = loop {
+            size = mem::size_of::<kinfo_proc>();
+            // SAFETY: mib holds KINFO_MIB_LEN ints, and kp is writable for the size
+            // bytes sysctl is told it has. Nothing is written, so the new value is null
+            let ret = unsafe {
+                self::libc::sysctl(mib.as_mut_ptr(), KINFO_MIB_LEN, &mut kp as *mut _ as *mut c_void,
+                                   &mut size, ptr::null_mut(), 0)
+            };
+            if ret != -1 {
+                break Ok(());
+            }
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() != Some(self::libc::EINTR) {
+                break Err(e);
+            }
+        };
+
+        trace!(pid, ok = res.is_ok(), size, "read kinfo_proc with sysctl");
+
+        match res {
+            // The process is gone, or never existed
+            Err(ref e) if e.raw_os_error() == Some(self::libc::ESRCH) => return Err(CttyError::NotFound),
+            Err(e) => return Err(match e.raw_os_error() {
+                Some(errno @ self::libc::EPERM) | Some(errno @ self::libc::EACCES) => {
+                    CttyError::SystemPermissionFailure(errno)
+                },
+                _ => CttyError::IOError(e)
+            }),
+            Ok(()) => {}
+        }
+
+        // Some systems report a missing process by returning nothing instead
+        if size == 0 || kinfo_ctty(&kp) == 0 {
+            return Err(CttyError::NotFound);
+        }
+        Ok(Dev(kinfo_ctty(&kp)))
+    }
+
+    /// Reads the ctty of the process with the given pid from its kinfo_proc, via libkvm
+    ///
+    /// Returns what kinfo_ctty reports (so 0 for no ctty), or None if libkvm couldn't
+    /// open the running kernel or find the process, in which case the sysctl decides.
+    #[cfg(all(target_os = "freebsd", feature = "kvm"))]
+    fn kvm_ctty_dev_for_pid(pid: u32) -> Option<u64> {
+        let mut errbuf = [0 as c_char; KVM_ERRBUF_LEN];
+        // SAFETY: NULL files select the running kernel, and errbuf has the
+        // _POSIX2_LINE_MAX bytes kvm_openfiles may write an error message to
+        let kd = unsafe {
+            kvm_openfiles(ptr::null(), ptr::null(), ptr::null(), self::libc::O_RDONLY,
+                          errbuf.as_mut_ptr())
+        };
+        if kd.is_null() {
+            #[cfg(feature = "tracing")]
+            {
+                // SAFETY: kvm_openfiles NUL-terminates the message it leaves in errbuf,
+                // and errbuf started out zeroed in case it left none
+                let err = unsafe { CStr::from_ptr(errbuf.as_ptr()) };
+                debug!(error = %err.to_string_lossy(), "kvm_openfiles failed, using sysctl");
+            }
+            return None;
+        }
+
+        let mut count: c_int = 0;
+        // SAFETY: kd was opened above, and count is writable
+        let procs = unsafe { kvm_getprocs(kd, KERN_PROC_PID, pid as c_int, &mut count) };
+        let tdev = if procs.is_null() || count < 1 {
+            None
+        } else {
+            // SAFETY: kvm_getprocs returned count (at least 1) kinfo_procs at procs, which
+            // stay valid until kd is closed
+            Some(kinfo_ctty(unsafe { &*procs }))
+        };
+        trace!(pid, ?tdev, "read kinfo_proc with kvm_getprocs");
+
+        // SAFETY: kd is open, and nothing from it is used after this
+        unsafe { kvm_close(kd) };
+        tdev
+    }
+
+    /// Returns the dev_t corresponding to the controlling tty of the current process's parent
+    pub fn get_parent_ctty_dev() -> Result<Dev, CttyError> {
+        // SAFETY: getppid has no preconditions and can't fail
+        get_ctty_dev_for_pid(unsafe { self::libc::getppid() } as u32)
+    }
+
+    /// Returns the major number of the given dev_t
+    ///
+    /// On FreeBSD, devfs allocates device numbers dynamically, so the major carries
+    /// no fixed meaning. On macOS, pseudo ttys share a single major (typically 16),
+    /// while NetBSD and OpenBSD use fixed per-driver majors.
+    pub fn major(dev: Dev) -> u32 {
+        self::libc::major(dev.0 as dev_t) as u32
+    }
+
+    /// Returns the minor number of the given dev_t
+    ///
+    /// On FreeBSD this is an opaque devfs identifier. On macOS, this is the N in
+    /// /dev/ttysN for pseudo ttys.
+    pub fn minor(dev: Dev) -> u32 {
+        self::libc::minor(dev.0 as dev_t) as u32
+    }
+
+    /// Returns whether the given dev_t is a pseudo tty (as used by ssh, tmux, etc.)
+    pub fn is_pty(dev: Dev) -> bool {
+        // Device numbers don't identify ptys here, but their names do: pts/N on
+        // FreeBSD and NetBSD, ttysNNN on macOS, and legacy BSD ttypN elsewhere
+        match dev_name(dev) {
+            Some(name) => {
+                name.starts_with(b"pts/") || name.starts_with(b"ttyp") ||
+                    (cfg!(target_os = "macos") && name.starts_with(b"ttys"))
+            },
+            None => false
+        }
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t
+    pub fn get_path_for_dev_buf(dev: Dev) -> Result<PathBuf, CttyError> {
+        get_path_for_dev_in(dev, Path::new("/dev"))
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t,
+    /// under dev_root instead of /dev
+    ///
+    /// The name still comes from devname, which reports it relative to the system's
+    /// devfs, so dev_root should mirror its layout. A relative dev_root is used as
+    /// given, and the returned path is relative too.
+    ///
+    /// devname goes by the device number alone, so the path is returned even if
+    /// there's no node there. Inside a FreeBSD jail, the devfs ruleset can hide the
+    /// terminal's node, and the path is then only good for display.
+    pub fn get_path_for_dev_in(dev: Dev, dev_root: &Path) -> Result<PathBuf, CttyError> {
+        let name = dev_name(dev).ok_or(CttyError::NotFound)?;
+
+        // Append the name to the dev root and return it
+        let path = dev_root.join(OsString::from_vec(name));
+        #[cfg(feature = "tracing")]
+        {
+            if !path.exists() {
+                debug!(path = %path.display(), "no device node at path, e.g. hidden from a jail");
+            }
+        }
+        Ok(path)
+    }
+
+    /// Returns the full paths of every tty or pseudo tty that corresponds with the
+    /// given dev_t
+    ///
+    /// devname only reports a single name per device, so this returns at most one path.
+    pub fn get_paths_for_dev(dev: Dev) -> Result<Vec<PathBuf>, CttyError> {
+        get_path_for_dev_buf(dev).map(|path| vec![path])
+    }
+
+    /// Returns the name of the tty or pseudo tty that corresponds with the given dev_t,
+    /// relative to /dev/ (e.g. pts/3), as shown by `who` and `ps`
+    pub fn get_name_for_dev(dev: Dev) -> Result<String, CttyError> {
+        // devname already reports the name without the /dev/ prefix
+        let name = dev_name(dev).ok_or(CttyError::NotFound)?;
+        Ok(String::from_utf8_lossy(&name).into_owned())
+    }
+
+    /// Returns the name of the device with the given dev_t, relative to /dev/
+    #[cfg(any(target_os = "freebsd", target_os = "dragonfly", target_os = "macos"))]
+    fn dev_name(dev: Dev) -> Option<Vec<u8>> {
+        // Zero the buffer up front so its contents are always well-defined
+        let mut buf = [0u8; 256];
+        // SAFETY: buf is writable for buf.len() bytes, and devname_r doesn't hold on to
+        // it after returning. The length is an int on FreeBSD and macOS, but a size_t
+        // on DragonFly
+        let res: *mut c_char = unsafe {
+            devname_r(dev.0 as dev_t, S_IFCHR, buf.as_mut_ptr(), buf.len() as _)
+        };
+
+        // On success the name is written to buf, so refuse to read from anywhere else.
+        // That covers NULL too, and leaves only buf itself to read, with no raw deref
+        if !buf.as_ptr_range().contains(&(res as *const u8)) {
+            return None;
+        }
+        let name = CStr::from_bytes_until_nul(&buf[res as usize - buf.as_ptr() as usize..]).ok()?;
+
+        // On failure, result will be '?' or '#' followed by the number, depending on OS
+        match name.to_bytes().first() {
+            None | Some(b'?') | Some(b'#') => None,
+            // Copy the name out of the buffer without any lossy conversion
+            Some(_) => Some(name.to_bytes().to_vec())
+        }
+    }
+
+    /// Returns the name of the device with the given dev_t, relative to /dev/
+    #[cfg(target_os = "netbsd")]
+    fn dev_name(dev: Dev) -> Option<Vec<u8>> {
+        let mut buf = [0u8; 256];
+        // SAFETY: buf is writable for buf.len() bytes, and devname_r doesn't hold on to
+        // it after returning
+        let res = unsafe {
+            devname_r(dev.0 as dev_t, S_IFCHR, buf.as_mut_ptr() as *mut c_char, buf.len())
+        };
+        // NetBSD's devname_r reports failure through its return value
+        if res != 0 {
+            return None;
+        }
+
+        // A name that filled buf without a NUL is refused rather than read past
+        let name = CStr::from_bytes_until_nul(&buf).ok()?;
+        Some(name.to_bytes().to_vec())
+    }
+
+    /// Returns the name of the device with the given dev_t, relative to /dev/
+    #[cfg(target_os = "openbsd")]
+    fn dev_name(dev: Dev) -> Option<Vec<u8>> {
+        // OpenBSD has no devname_r, so copy out of devname's static buffer instead
+        // SAFETY: devname has no preconditions, and returns either NULL or a
+        // NUL-terminated string
+        let res: *mut c_char = unsafe { devname(dev.0 as dev_t, S_IFCHR) };
+        if res.is_null() {
+            return None;
+        }
+
+        // SAFETY: res isn't NULL, so it points to devname's NUL-terminated result. That
+        // lives in a static buffer, which is copied out before anything can reuse it
+        let name = unsafe { CStr::from_ptr(res) }.to_bytes().to_vec();
+        // On failure, result will be "??"
+        match name.first() {
+            None | Some(b'?') => None,
+            Some(_) => Some(name)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_get_ctty_dev_for_missing_pid() {
+            // Well above any pid these systems hand out
+            let res = get_ctty_dev_for_pid(i32::MAX as u32);
+            assert!(matches!(res, Err(CttyError::NotFound)));
+        }
+
+        #[test]
+        fn test_get_path_for_hidden_dev() {
+            // Stand in for a jail's /dev, with devfs rules that hide every terminal
+            let root = std::env::temp_dir().join(format!("ctty-jail-{}", std::process::id()));
+            std::fs::create_dir_all(&root).unwrap();
+            let dev = get_ctty_dev().unwrap();
+            let path = get_path_for_dev_in(dev, &root);
+            std::fs::remove_dir_all(&root).unwrap();
+
+            assert_eq!(path.unwrap(), root.join(get_name_for_dev(dev).unwrap()));
+        }
+
+        #[cfg(all(target_os = "freebsd", feature = "kvm"))]
+        #[test]
+        fn test_kvm_matches_sysctl() {
+            let pid = std::process::id();
+            // Opening the running kernel needs /dev/mem, so there may be nothing to compare
+            if let Some(tdev) = kvm_ctty_dev_for_pid(pid) {
+                let sysctl = match sysctl_ctty_dev_for_pid(pid) {
+                    Ok(dev) => dev.0,
+                    Err(CttyError::NotFound) => 0,
+                    Err(e) => panic!("sysctl failed: {}", e)
+                };
+                assert_eq!(tdev, sysctl);
+            }
+        }
+
+        #[test]
+        fn test_kinfo_ctty_nodev() {
+            // SAFETY: kinfo_proc is a plain C struct, for which all zeroes is a valid value
+            let mut kp: kinfo_proc = unsafe { mem::zeroed() };
+            assert_eq!(kinfo_ctty(&kp), 0);
+
+            // What a process without a ctty reports, which mustn't come out as a device
+            #[cfg(target_os = "freebsd")]
+            { kp.ki_tdev = !0; }
+            #[cfg(target_os = "dragonfly")]
+            { kp.kp_tdev = !0; }
+            #[cfg(target_os = "macos")]
+            { kp.kp_eproc.e_tdev = !0; }
+            #[cfg(any(target_os = "netbsd", target_os = "openbsd"))]
+            { kp.p_tdev = !0; }
+            assert_eq!(kinfo_ctty(&kp), 0);
+        }
+
+        #[test]
+        fn test_dev_name_unresolvable() {
+            // NODEV has no node, so devname_r reports failure (or '?' or '#') for it
+            let nodev = Dev(u64::MAX);
+            assert_eq!(dev_name(nodev), None);
+            assert!(matches!(get_path_for_dev_buf(nodev), Err(CttyError::NotFound)));
+            assert!(matches!(get_name_for_dev(nodev), Err(CttyError::NotFound)));
+        }
+
+        #[test]
+        fn test_major_minor() {
+            let dev = Dev(super::libc::makedev(16, 3) as u64);
+            assert_eq!((major(dev), minor(dev)), (16, 3));
+        }
+    }
+}
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly", target_os = "macos", target_os = "netbsd",
+          target_os = "openbsd"))]
+pub use bsd::*;
+
+// illumos and Solaris expose a binary psinfo_t through /proc instead of a text stat
+// file, so read just enough of the struct to get at pr_ttydev.
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+mod solaris {
+    use std::fs::{self, File};
+    use std::io;
+    use std::io::prelude::*;
+    use std::mem;
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+
+    use ::{CttyError, Dev};
+
+    extern crate libc;
+    use self::libc::{c_int, dev_t, gid_t, pid_t, size_t, uid_t, uintptr_t};
+
+    /// pr_ttydev value for a process without a controlling tty
+    const PRNODEV: dev_t = !0;
+
+    /// Leading fields of psinfo_t from <sys/procfs.h>, up to and including pr_ttydev
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct PsinfoPrefix {
+        pr_flag: c_int,
+        pr_nlwp: c_int,
+        pr_pid: pid_t,
+        pr_ppid: pid_t,
+        pr_pgid: pid_t,
+        pr_sid: pid_t,
+        pr_uid: uid_t,
+        pr_euid: uid_t,
+        pr_gid: gid_t,
+        pr_egid: gid_t,
+        pr_addr: uintptr_t,
+        pr_size: size_t,
+        pr_rssize: size_t,
+        pr_pad1: size_t,
+        pr_ttydev: dev_t
+    }
+
+    /// Returns the dev_t corresponding to the current process's controlling tty
+    pub fn get_ctty_dev() -> Result<Dev, CttyError> {
+        get_ctty_dev_from_psinfo("/proc/self/psinfo")
+    }
+
+    /// Returns the dev_t corresponding to the controlling tty of the process with the given pid
+    pub fn get_ctty_dev_for_pid(pid: u32) -> Result<Dev, CttyError> {
+        match get_ctty_dev_from_psinfo(&format!("/proc/{}/psinfo", pid)) {
+            // The process doesn't exist (or has already exited)
+            Err(CttyError::IOError(ref e)) if e.kind() == io::ErrorKind::NotFound => {
+                Err(CttyError::NotFound)
+            },
+            res => res
+        }
+    }
+
+    /// Returns the dev_t corresponding to the controlling tty of the current process's parent
+    pub fn get_parent_ctty_dev() -> Result<Dev, CttyError> {
+        get_ctty_dev_for_pid(unsafe { self::libc::getppid() } as u32)
+    }
+
+    /// Reads pr_ttydev out of the given psinfo file
+    fn get_ctty_dev_from_psinfo(path: &str) -> Result<Dev, CttyError> {
+        let mut psinfo_f = File::open(path).map_err(map_io_error)?;
+        let mut psinfo = Vec::new();
+        psinfo_f.read_to_end(&mut psinfo).map_err(map_io_error)?;
+        trace!(path, len = psinfo.len(), "read psinfo");
+        dev_from_psinfo(&psinfo)
+    }
+
+    /// Converts an IO error from reading /proc into a CttyError, singling out permission failures
+    fn map_io_error(e: io::Error) -> CttyError {
+        match e.kind() {
+            // An error that only carries a kind is reported as EACCES
+            io::ErrorKind::PermissionDenied => {
+                CttyError::SystemPermissionFailure(e.raw_os_error().unwrap_or(self::libc::EACCES))
+            },
+            _ => CttyError::IOError(e)
+        }
+    }
+
+    /// Decodes pr_ttydev from the contents of a psinfo file
+    fn dev_from_psinfo(psinfo: &[u8]) -> Result<Dev, CttyError> {
+        if psinfo.len() < mem::size_of::<PsinfoPrefix>() {
+            return Err(CttyError::SystemDataParseFailure);
+        }
+
+        // The buffer is long enough for the prefix, but may not be suitably aligned
+        let prefix = unsafe { ptr::read_unaligned(psinfo.as_ptr() as *const PsinfoPrefix) };
+        trace!(pr_ttydev = prefix.pr_ttydev, "decoded psinfo");
+        if prefix.pr_ttydev == PRNODEV {
+            return Err(CttyError::NotFound);
+        }
+        Ok(Dev(prefix.pr_ttydev as u64))
+    }
+
+    /// Returns the major number of the given dev_t
+    pub fn major(dev: Dev) -> u32 {
+        unsafe { self::libc::major(dev.0 as dev_t) as u32 }
+    }
+
+    /// Returns the minor number of the given dev_t
+    pub fn minor(dev: Dev) -> u32 {
+        unsafe { self::libc::minor(dev.0 as dev_t) as u32 }
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t
+    pub fn get_path_for_dev_buf(dev: Dev) -> Result<PathBuf, CttyError> {
+        get_path_for_dev_in(dev, Path::new("/dev"))
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t,
+    /// searching dev_root instead of /dev
+    ///
+    /// A relative dev_root is used as given, so it's resolved against the current
+    /// directory and the returned path is relative too.
+    pub fn get_path_for_dev_in(dev: Dev, dev_root: &Path) -> Result<PathBuf, CttyError> {
+        matching_devs(dev, dev_root).map(|mut paths| paths.swap_remove(0))
+    }
+
+    /// Returns the full paths of every tty or pseudo tty that corresponds with the
+    /// given dev_t, in the order they were found
+    pub fn get_paths_for_dev(dev: Dev) -> Result<Vec<PathBuf>, CttyError> {
+        matching_devs(dev, Path::new("/dev"))
+    }
+
+    /// Returns every device node under dev_root that corresponds with the given dev_t
+    fn matching_devs(dev: Dev, dev_root: &Path) -> Result<Vec<PathBuf>, CttyError> {
+        let mut paths = Vec::new();
+
+        // Check pseudo ttys, serial lines, and the console for a match
+        for dir in &["pts", "term"] {
+            let entries = match fs::read_dir(dev_root.join(dir)) {
+                Ok(e) => e,
+                Err(_) => { // Silently continue
+                    continue;
+                }
+            };
+
+            for entry in entries.filter_map(|e| e.ok()) {
+                if dev_matches(&entry.path(), dev) {
+                    paths.push(entry.path());
+                }
+            }
+        }
+
+        let console = dev_root.join("console");
+        if dev_matches(&console, dev) {
+            paths.push(console);
+        }
+
+        if paths.is_empty() {
+            return Err(CttyError::NotFound);
+        }
+        Ok(paths)
+    }
+
+    /// Returns the name of the tty or pseudo tty that corresponds with the given dev_t,
+    /// relative to /dev/ (e.g. pts/3), as shown by `who` and `ps`
+    pub fn get_name_for_dev(dev: Dev) -> Result<String, CttyError> {
+        let path = get_path_for_dev_buf(dev)?;
+        // Every directory searched is under /dev
+        let name = path.strip_prefix("/dev").map_err(|_| CttyError::SystemDataParseFailure)?;
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    /// Returns whether the given dev_t is a pseudo tty (as used by ssh, tmux, etc.)
+    pub fn is_pty(dev: Dev) -> bool {
+        match get_path_for_dev_buf(dev) {
+            Ok(path) => path.starts_with("/dev/pts"),
+            Err(_) => false
+        }
+    }
+
+    /// Checks whether the character device at path has the given dev_t
+    fn dev_matches(path: &Path, dev: Dev) -> bool {
+        match fs::metadata(path) {
+            Ok(m) => m.file_type().is_char_device() && m.rdev() == dev.0,
+            Err(_) => false
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::slice;
+
+        /// Returns the bytes of a psinfo file whose pr_ttydev is tty, offset by one byte
+        /// so the prefix isn't aligned
+        fn psinfo_with_tty(tty: dev_t) -> Vec<u8> {
+            let mut prefix: PsinfoPrefix = unsafe { mem::zeroed() };
+            prefix.pr_ttydev = tty;
+            let bytes = unsafe {
+                slice::from_raw_parts(&prefix as *const _ as *const u8, mem::size_of::<PsinfoPrefix>())
+            };
+            // The rest of psinfo_t follows the prefix
+            let mut psinfo = vec![0u8];
+            psinfo.extend_from_slice(bytes);
+            psinfo.extend_from_slice(&[0u8; 64]);
+            psinfo
+        }
+
+        #[test]
+        fn test_dev_from_psinfo() {
+            let dev = unsafe { self::libc::makedev(24, 3) };
+            assert_eq!(dev_from_psinfo(&psinfo_with_tty(dev)[1..]), Ok(Dev(dev as u64)));
+        }
+
+        #[test]
+        fn test_dev_from_psinfo_no_tty() {
+            assert_eq!(dev_from_psinfo(&psinfo_with_tty(PRNODEV)[1..]), Err(CttyError::NotFound));
+        }
+
+        #[test]
+        fn test_map_io_error() {
+            let denied = map_io_error(io::Error::from_raw_os_error(self::libc::EACCES));
+            assert!(matches!(denied, CttyError::SystemPermissionFailure(self::libc::EACCES)));
+
+            let kind_only = map_io_error(io::Error::from(io::ErrorKind::PermissionDenied));
+            assert!(matches!(kind_only, CttyError::SystemPermissionFailure(self::libc::EACCES)));
+
+            // A missing process is still an IO error here, for get_ctty_dev_for_pid to map
+            let missing = map_io_error(io::Error::from(io::ErrorKind::NotFound));
+            assert!(matches!(missing, CttyError::IOError(ref e) if e.kind() == io::ErrorKind::NotFound));
+        }
+
+        #[test]
+        fn test_dev_from_short_psinfo() {
+            let psinfo = psinfo_with_tty(0);
+            let short = &psinfo[1..mem::size_of::<PsinfoPrefix>()];
+            assert_eq!(dev_from_psinfo(short), Err(CttyError::SystemDataParseFailure));
+            assert_eq!(dev_from_psinfo(&[]), Err(CttyError::SystemDataParseFailure));
+        }
+    }
+}
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+pub use solaris::*;
+
+// AIX has an SVR4-style /proc like Solaris, but its psinfo_t is made of fixed-width
+// fields, and pr_ttydev holds a 64-bit device number rather than a dev_t.
+#[cfg(target_os = "aix")]
+mod aix {
+    use std::fs::{self, File};
+    use std::io;
+    use std::io::prelude::*;
+    use std::mem;
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+
+    use ::{CttyError, Dev};
+
+    extern crate libc;
+
+    /// pr_ttydev value for a process without a controlling tty
+    const PRNODEV: u64 = !0;
+
+    /// Leading fields of psinfo_t from <sys/procfs.h>, up to and including pr_ttydev
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct PsinfoPrefix {
+        pr_flag: u32,
+        pr_flag2: u32,
+        pr_nlwp: u32,
+        pr_pad1: u32,
+        pr_uid: u64,
+        pr_euid: u64,
+        pr_gid: u64,
+        pr_egid: u64,
+        pr_pid: u64,
+        pr_ppid: u64,
+        pr_pgid: u64,
+        pr_sid: u64,
+        pr_ttydev: u64
+    }
+
+    /// Returns the dev_t corresponding to the current process's controlling tty
+    pub fn get_ctty_dev() -> Result<Dev, CttyError> {
+        // AIX's /proc has no self link
+        get_ctty_dev_from_psinfo(&format!("/proc/{}/psinfo", std::process::id()))
+    }
+
+    /// Returns the dev_t corresponding to the controlling tty of the process with the given pid
+    pub fn get_ctty_dev_for_pid(pid: u32) -> Result<Dev, CttyError> {
+        match get_ctty_dev_from_psinfo(&format!("/proc/{}/psinfo", pid)) {
+            // The process doesn't exist (or has already exited)
+            Err(CttyError::IOError(ref e)) if e.kind() == io::ErrorKind::NotFound => {
+                Err(CttyError::NotFound)
+            },
+            res => res
+        }
+    }
+
+    /// Returns the dev_t corresponding to the controlling tty of the current process's parent
+    pub fn get_parent_ctty_dev() -> Result<Dev, CttyError> {
+        get_ctty_dev_for_pid(unsafe { self::libc::getppid() } as u32)
+    }
+
+    /// Reads pr_ttydev out of the given psinfo file
+    fn get_ctty_dev_from_psinfo(path: &str) -> Result<Dev, CttyError> {
+        let mut psinfo_f = File::open(path).map_err(map_io_error)?;
+        let mut psinfo = Vec::new();
+        psinfo_f.read_to_end(&mut psinfo).map_err(map_io_error)?;
+        trace!(path, len = psinfo.len(), "read psinfo");
+        dev_from_psinfo(&psinfo)
+    }
+
+    /// Converts an IO error from reading /proc into a CttyError, singling out permission failures
+    fn map_io_error(e: io::Error) -> CttyError {
+        match e.kind() {
+            // An error that only carries a kind is reported as EACCES
+            io::ErrorKind::PermissionDenied => {
+                CttyError::SystemPermissionFailure(e.raw_os_error().unwrap_or(self::libc::EACCES))
+            },
+            _ => CttyError::IOError(e)
+        }
+    }
+
+    /// Decodes pr_ttydev from the contents of a psinfo file
+    fn dev_from_psinfo(psinfo: &[u8]) -> Result<Dev, CttyError> {
+        if psinfo.len() < mem::size_of::<PsinfoPrefix>() {
+            return Err(CttyError::SystemDataParseFailure);
+        }
+
+        // The buffer is long enough for the prefix, but may not be suitably aligned
+        let prefix = unsafe { ptr::read_unaligned(psinfo.as_ptr() as *const PsinfoPrefix) };
+        trace!(pr_ttydev = prefix.pr_ttydev, "decoded psinfo");
+        if prefix.pr_ttydev == PRNODEV || prefix.pr_ttydev == 0 {
+            return Err(CttyError::NotFound);
+        }
+        Ok(to_dev(prefix.pr_ttydev))
+    }
+
+    /// Converts a device number to a dev_t, with the major in the high 16 bits and the
+    /// minor in the low 16 bits
+    ///
+    /// /proc reports devices in the 64-bit format, which keeps the major in the high
+    /// 32 bits (alongside a flag bit) instead. Numbers already in dev_t form are
+    /// returned as is.
+    fn to_dev(raw: u64) -> Dev {
+        if raw >> 32 == 0 {
+            return Dev(raw);
+        }
+        Dev(((raw & 0x0000_ffff_0000_0000) >> 16) | (raw & 0xffff))
+    }
+
+    /// Returns the major number of the given dev_t
+    ///
+    /// AIX assigns majors dynamically, so they carry no fixed meaning.
+    pub fn major(dev: Dev) -> u32 {
+        (dev.0 >> 16) as u32
+    }
+
+    /// Returns the minor number of the given dev_t
+    pub fn minor(dev: Dev) -> u32 {
+        (dev.0 & 0xffff) as u32
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t
+    pub fn get_path_for_dev_buf(dev: Dev) -> Result<PathBuf, CttyError> {
+        get_path_for_dev_in(dev, Path::new("/dev"))
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t,
+    /// searching dev_root instead of /dev
+    ///
+    /// A relative dev_root is used as given, so it's resolved against the current
+    /// directory and the returned path is relative too.
+    pub fn get_path_for_dev_in(dev: Dev, dev_root: &Path) -> Result<PathBuf, CttyError> {
+        matching_devs(dev, dev_root).map(|mut paths| paths.swap_remove(0))
+    }
+
+    /// Returns the full paths of every tty or pseudo tty that corresponds with the
+    /// given dev_t, in the order they were found
+    pub fn get_paths_for_dev(dev: Dev) -> Result<Vec<PathBuf>, CttyError> {
+        matching_devs(dev, Path::new("/dev"))
+    }
+
+    /// Returns every device node under dev_root that corresponds with the given dev_t
+    fn matching_devs(dev: Dev, dev_root: &Path) -> Result<Vec<PathBuf>, CttyError> {
+        let mut paths = Vec::new();
+
+        // Check pseudo ttys first, then serial lines (tty*), LPAR virtual terminals
+        // (vty*), and the console
+        if let Ok(entries) = fs::read_dir(dev_root.join("pts")) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                if dev_matches(&entry.path(), dev) {
+                    paths.push(entry.path());
+                }
+            }
+        }
+        if let Ok(entries) = fs::read_dir(dev_root) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                let is_tty = name.starts_with("tty") || name.starts_with("vty") || name == "console";
+                if is_tty && dev_matches(&entry.path(), dev) {
+                    paths.push(entry.path());
+                }
+            }
+        }
+
+        if paths.is_empty() {
+            return Err(CttyError::NotFound);
+        }
+        Ok(paths)
+    }
+
+    /// Returns the name of the tty or pseudo tty that corresponds with the given dev_t,
+    /// relative to /dev/ (e.g. pts/3), as shown by `who` and `ps`
+    pub fn get_name_for_dev(dev: Dev) -> Result<String, CttyError> {
+        let path = get_path_for_dev_buf(dev)?;
+        // Every directory searched is under /dev
+        let name = path.strip_prefix("/dev").map_err(|_| CttyError::SystemDataParseFailure)?;
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    /// Returns whether the given dev_t is a pseudo tty (as used by ssh, tmux, etc.)
+    pub fn is_pty(dev: Dev) -> bool {
+        match get_path_for_dev_buf(dev) {
+            Ok(path) => path.starts_with("/dev/pts"),
+            Err(_) => false
+        }
+    }
+
+    /// Checks whether the character device at path has the given dev_t
+    fn dev_matches(path: &Path, dev: Dev) -> bool {
+        match fs::metadata(path) {
+            Ok(m) => m.file_type().is_char_device() && to_dev(m.rdev()) == dev,
+            Err(_) => false
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::slice;
+
+        /// Returns the bytes of a psinfo file whose pr_ttydev is tty, offset by one byte
+        /// so the prefix isn't aligned
+        fn psinfo_with_tty(tty: u64) -> Vec<u8> {
+            let mut prefix: PsinfoPrefix = unsafe { mem::zeroed() };
+            prefix.pr_ttydev = tty;
+            let bytes = unsafe {
+                slice::from_raw_parts(&prefix as *const _ as *const u8, mem::size_of::<PsinfoPrefix>())
+            };
+            // The rest of psinfo_t follows the prefix
+            let mut psinfo = vec![0u8];
+            psinfo.extend_from_slice(bytes);
+            psinfo.extend_from_slice(&[0u8; 64]);
+            psinfo
+        }
+
+        #[test]
+        fn test_dev_from_psinfo() {
+            let psinfo = psinfo_with_tty(0x8000_0017_0000_0005);
+            assert_eq!(dev_from_psinfo(&psinfo[1..]), Ok(Dev(0x17_0005)));
+        }
+
+        #[test]
+        fn test_dev_from_psinfo_no_tty() {
+            assert_eq!(dev_from_psinfo(&psinfo_with_tty(PRNODEV)[1..]), Err(CttyError::NotFound));
+            assert_eq!(dev_from_psinfo(&psinfo_with_tty(0)[1..]), Err(CttyError::NotFound));
+        }
+
+        #[test]
+        fn test_map_io_error() {
+            let denied = map_io_error(io::Error::from_raw_os_error(self::libc::EACCES));
+            assert!(matches!(denied, CttyError::SystemPermissionFailure(self::libc::EACCES)));
+
+            let kind_only = map_io_error(io::Error::from(io::ErrorKind::PermissionDenied));
+            assert!(matches!(kind_only, CttyError::SystemPermissionFailure(self::libc::EACCES)));
+
+            // A missing process is still an IO error here, for get_ctty_dev_for_pid to map
+            let missing = map_io_error(io::Error::from(io::ErrorKind::NotFound));
+            assert!(matches!(missing, CttyError::IOError(ref e) if e.kind() == io::ErrorKind::NotFound));
+        }
+
+        #[test]
+        fn test_dev_from_short_psinfo() {
+            let psinfo = psinfo_with_tty(0x17_0005);
+            let short = &psinfo[1..mem::size_of::<PsinfoPrefix>()];
+            assert_eq!(dev_from_psinfo(short), Err(CttyError::SystemDataParseFailure));
+            assert_eq!(dev_from_psinfo(&[]), Err(CttyError::SystemDataParseFailure));
+        }
+
+        #[test]
+        fn test_to_dev() {
+            // A 64-bit device number has the DEV64 flag in its top bit
+            assert_eq!(to_dev(0x8000_0017_0000_0005), Dev(0x17_0005));
+            assert_eq!(to_dev(0x17_0005), Dev(0x17_0005));
+            assert_eq!((major(Dev(0x17_0005)), minor(Dev(0x17_0005))), (0x17, 5));
+        }
+    }
+}
+#[cfg(target_os = "aix")]
+pub use aix::*;
+
+// Haiku doesn't record the ctty anywhere it can be queried, so its path is found with
+// the POSIX terminal APIs and stat'd to get the device.
+#[cfg(target_os = "haiku")]
+mod haiku {
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+    use std::path::{Path, PathBuf};
+
+    use ::{CttyError, Dev};
+
+    /// Returns the dev_t corresponding to the current process's controlling tty
+    ///
+    /// This stats the ttyname of whichever of stdin, stdout, and stderr is the ctty,
+    /// or if they're all redirected, the ttyname of the terminal ctermid names.
+    pub fn get_ctty_dev() -> Result<Dev, CttyError> {
+        let path = match ::unix::ttyname_of_session_fd() {
+            Ok(path) => path,
+            Err(_) => ::unix::ttyname_of_ctermid()?
+        };
+        trace!(path = %path.display(), "found controlling tty path");
+
+        let m = fs::metadata(&path)?;
+        if !m.file_type().is_char_device() {
+            return Err(CttyError::NotFound);
+        }
+        Ok(Dev(m.rdev()))
+    }
+
+    /// Returns the dev_t corresponding to the controlling tty of the process with the given pid
+    ///
+    /// Only the current process's ctty can be found on Haiku, so any other pid fails
+    /// with `CttyError::Unsupported`.
+    pub fn get_ctty_dev_for_pid(pid: u32) -> Result<Dev, CttyError> {
+        if pid != std::process::id() {
+            return Err(CttyError::Unsupported);
+        }
+        get_ctty_dev()
+    }
+
+    /// Always returns `CttyError::Unsupported`, since only the current process's ctty
+    /// can be found on Haiku
+    pub fn get_parent_ctty_dev() -> Result<Dev, CttyError> {
+        Err(CttyError::Unsupported)
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t
+    pub fn get_path_for_dev_buf(dev: Dev) -> Result<PathBuf, CttyError> {
+        get_path_for_dev_in(dev, Path::new("/dev"))
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t,
+    /// searching dev_root instead of /dev
+    ///
+    /// A relative dev_root is used as given, so it's resolved against the current
+    /// directory and the returned path is relative too.
+    pub fn get_path_for_dev_in(dev: Dev, dev_root: &Path) -> Result<PathBuf, CttyError> {
+        matching_devs(dev, dev_root).map(|mut paths| paths.swap_remove(0))
+    }
+
+    /// Returns the full paths of every tty or pseudo tty that corresponds with the
+    /// given dev_t, in the order they were found
+    pub fn get_paths_for_dev(dev: Dev) -> Result<Vec<PathBuf>, CttyError> {
+        matching_devs(dev, Path::new("/dev"))
+    }
+
+    /// Returns every device node under dev_root that corresponds with the given dev_t
+    fn matching_devs(dev: Dev, dev_root: &Path) -> Result<Vec<PathBuf>, CttyError> {
+        let mut paths = Vec::new();
+
+        // Check pseudo tty slaves (tt/pN), serial ports, and the console for a match
+        for dir in &["tt", "ports"] {
+            let entries = match fs::read_dir(dev_root.join(dir)) {
+                Ok(e) => e,
+                Err(_) => continue
+            };
+
+            for entry in entries.filter_map(|e| e.ok()) {
+                if dev_matches(&entry.path(), dev) {
+                    paths.push(entry.path());
+                }
+            }
+        }
+
+        let console = dev_root.join("console");
+        if dev_matches(&console, dev) {
+            paths.push(console);
+        }
+
+        if paths.is_empty() {
+            return Err(CttyError::NotFound);
+        }
+        Ok(paths)
+    }
+
+    /// Returns the name of the tty or pseudo tty that corresponds with the given dev_t,
+    /// relative to /dev/ (e.g. tt/p0)
+    pub fn get_name_for_dev(dev: Dev) -> Result<String, CttyError> {
+        let path = get_path_for_dev_buf(dev)?;
+        // Every directory searched is under /dev
+        let name = path.strip_prefix("/dev").map_err(|_| CttyError::SystemDataParseFailure)?;
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    /// Returns whether the given dev_t is a pseudo tty (as used by ssh, Terminal, etc.)
+    pub fn is_pty(dev: Dev) -> bool {
+        match get_path_for_dev_buf(dev) {
+            Ok(path) => path.starts_with("/dev/tt"),
+            Err(_) => false
+        }
+    }
+
+    /// Checks whether the character device at path has the given dev_t
+    fn dev_matches(path: &Path, dev: Dev) -> bool {
+        match fs::metadata(path) {
+            Ok(m) => m.file_type().is_char_device() && m.rdev() == dev.0,
+            Err(_) => false
+        }
+    }
+}
+#[cfg(target_os = "haiku")]
+pub use haiku::*;
+
+// The Hurd's procfs doesn't report the ctty's device in /proc/<pid>/stat, so like on
+// Haiku, its path is found with the POSIX terminal APIs and stat'd to get the device.
+#[cfg(target_os = "hurd")]
+mod hurd {
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+    use std::path::{Path, PathBuf};
+
+    use ::{CttyError, Dev};
+
+    /// Returns the dev_t corresponding to the current process's controlling tty
+    ///
+    /// This stats the ttyname of whichever of stdin, stdout, and stderr is the ctty,
+    /// or if they're all redirected, the ttyname of the terminal ctermid names.
+    pub fn get_ctty_dev() -> Result<Dev, CttyError> {
+        let path = match ::unix::ttyname_of_session_fd() {
+            Ok(path) => path,
+            Err(_) => ::unix::ttyname_of_ctermid()?
+        };
+        trace!(path = %path.display(), "found controlling tty path");
+
+        let m = fs::metadata(&path)?;
+        if !m.file_type().is_char_device() {
+            return Err(CttyError::NotFound);
+        }
+        Ok(Dev(m.rdev()))
+    }
+
+    /// Returns the dev_t corresponding to the controlling tty of the process with the given pid
+    ///
+    /// Only the current process's ctty can be found on the Hurd, so any other pid fails
+    /// with `CttyError::Unsupported`.
+    pub fn get_ctty_dev_for_pid(pid: u32) -> Result<Dev, CttyError> {
+        if pid != std::process::id() {
+            return Err(CttyError::Unsupported);
+        }
+        get_ctty_dev()
+    }
+
+    /// Always returns `CttyError::Unsupported`, since only the current process's ctty
+    /// can be found on the Hurd
+    pub fn get_parent_ctty_dev() -> Result<Dev, CttyError> {
+        Err(CttyError::Unsupported)
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t
+    pub fn get_path_for_dev_buf(dev: Dev) -> Result<PathBuf, CttyError> {
+        get_path_for_dev_in(dev, Path::new("/dev"))
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t,
+    /// searching dev_root instead of /dev
+    ///
+    /// A relative dev_root is used as given, so it's resolved against the current
+    /// directory and the returned path is relative too.
+    pub fn get_path_for_dev_in(dev: Dev, dev_root: &Path) -> Result<PathBuf, CttyError> {
+        matching_devs(dev, dev_root).map(|mut paths| paths.swap_remove(0))
+    }
+
+    /// Returns the full paths of every tty or pseudo tty that corresponds with the
+    /// given dev_t, in the order they were found
+    pub fn get_paths_for_dev(dev: Dev) -> Result<Vec<PathBuf>, CttyError> {
+        matching_devs(dev, Path::new("/dev"))
+    }
+
+    /// Returns every device node under dev_root that corresponds with the given dev_t
+    fn matching_devs(dev: Dev, dev_root: &Path) -> Result<Vec<PathBuf>, CttyError> {
+        let mut paths = Vec::new();
+
+        // Check the virtual consoles and pseudo tty slaves (tty*), serial ports
+        // (com*), and the console for a match
+        let entries = fs::read_dir(dev_root).into_iter().flatten().filter_map(|e| e.ok());
+        for entry in entries {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let is_tty = name.starts_with("tty") || name.starts_with("com") || name == "console";
+            if is_tty && dev_matches(&entry.path(), dev) {
+                paths.push(entry.path());
+            }
+        }
+
+        if paths.is_empty() {
+            return Err(CttyError::NotFound);
+        }
+        Ok(paths)
+    }
+
+    /// Returns the name of the tty or pseudo tty that corresponds with the given dev_t,
+    /// relative to /dev/ (e.g. ttyp0)
+    pub fn get_name_for_dev(dev: Dev) -> Result<String, CttyError> {
+        let path = get_path_for_dev_buf(dev)?;
+        // Every directory searched is under /dev
+        let name = path.strip_prefix("/dev").map_err(|_| CttyError::SystemDataParseFailure)?;
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    /// Returns whether the given dev_t is a pseudo tty (as used by ssh, tmux, etc.)
+    pub fn is_pty(dev: Dev) -> bool {
+        match get_name_for_dev(dev) {
+            Ok(name) => is_pty_name(&name),
+            Err(_) => false
+        }
+    }
+
+    /// Checks whether a /dev entry is a BSD-style pseudo tty slave, e.g. ttyp0, as
+    /// opposed to a virtual console like tty1
+    fn is_pty_name(name: &str) -> bool {
+        name.starts_with("tty") && name[3..].starts_with(|c: char| c.is_ascii_lowercase())
+    }
+
+    /// Checks whether the character device at path has the given dev_t
+    fn dev_matches(path: &Path, dev: Dev) -> bool {
+        match fs::metadata(path) {
+            Ok(m) => m.file_type().is_char_device() && m.rdev() == dev.0,
+            Err(_) => false
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_is_pty_name() {
+            assert!(is_pty_name("ttyp0"));
+            assert!(is_pty_name("ttyqf"));
+            assert!(!is_pty_name("tty1"));
+            assert!(!is_pty_name("tty"));
+            assert!(!is_pty_name("console"));
+        }
+    }
+}
+#[cfg(target_os = "hurd")]
+pub use hurd::*;
+
+// Redox keeps no record of a process's ctty, and its terminals are served by schemes
+// (e.g. /scheme/pty/3) rather than living under /dev. The controlling tty is taken
+// to be whichever of stdin, stdout, and stderr belongs to the session.
+#[cfg(target_os = "redox")]
+mod redox {
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+
+    use ::{CttyError, Dev};
+
+    /// Returns the path and dev_t of the current process's controlling tty
+    fn get_ctty() -> Result<(PathBuf, Dev), CttyError> {
+        let path = ::unix::ttyname_of_session_fd()?;
+        let rdev = fs::metadata(&path)?.rdev();
+        trace!(path = %path.display(), rdev, "found controlling tty");
+        // Schemes that don't identify their devices report 0
+        if rdev == 0 {
+            return Err(CttyError::NotFound);
+        }
+        Ok((path, Dev(rdev)))
+    }
+
+    /// Returns the dev_t corresponding to the current process's controlling tty
+    pub fn get_ctty_dev() -> Result<Dev, CttyError> {
+        get_ctty().map(|(_, dev)| dev)
+    }
+
+    /// Returns the dev_t corresponding to the controlling tty of the process with the given pid
+    ///
+    /// Only the current process's ctty can be found on Redox, so any other pid fails
+    /// with `CttyError::Unsupported`.
+    pub fn get_ctty_dev_for_pid(pid: u32) -> Result<Dev, CttyError> {
+        if pid != std::process::id() {
+            return Err(CttyError::Unsupported);
+        }
+        get_ctty_dev()
+    }
+
+    /// Always returns `CttyError::Unsupported`, since only the current process's ctty
+    /// can be found on Redox
+    pub fn get_parent_ctty_dev() -> Result<Dev, CttyError> {
+        Err(CttyError::Unsupported)
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t
+    ///
+    /// Terminals can't be enumerated on Redox, so only the current process's ctty
+    /// is found; any other dev_t fails with `CttyError::NotFound`.
+    pub fn get_path_for_dev_buf(dev: Dev) -> Result<PathBuf, CttyError> {
+        match get_ctty() {
+            Ok((path, ctty_dev)) if ctty_dev == dev => Ok(path),
+            _ => Err(CttyError::NotFound)
+        }
+    }
+
+    /// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t,
+    /// if it lies under dev_root
+    ///
+    /// As with get_path_for_dev_buf, only the current process's ctty can be found. A
+    /// relative dev_root is used as given.
+    pub fn get_path_for_dev_in(dev: Dev, dev_root: &Path) -> Result<PathBuf, CttyError> {
+        let path = get_path_for_dev_buf(dev)?;
+        if !path.starts_with(dev_root) {
+            return Err(CttyError::NotFound);
+        }
+        Ok(path)
+    }
+
+    /// Returns the full paths of every tty or pseudo tty that corresponds with the
+    /// given dev_t, which is at most the current process's ctty on Redox
+    pub fn get_paths_for_dev(dev: Dev) -> Result<Vec<PathBuf>, CttyError> {
+        get_path_for_dev_buf(dev).map(|path| vec![path])
+    }
+
+    /// Returns the name of the tty or pseudo tty that corresponds with the given dev_t,
+    /// relative to /scheme/ (e.g. pty/3)
+    pub fn get_name_for_dev(dev: Dev) -> Result<String, CttyError> {
+        let path = get_path_for_dev_buf(dev)?;
+        let name = path.strip_prefix("/scheme").unwrap_or(&path);
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    /// Returns whether the given dev_t is a pseudo tty (as used by ssh, orbterm, etc.)
+    pub fn is_pty(dev: Dev) -> bool {
+        match get_path_for_dev_buf(dev) {
+            Ok(path) => path.starts_with("/scheme/pty"),
+            Err(_) => false
+        }
+    }
+}
+#[cfg(target_os = "redox")]
+pub use redox::*;
+
+// Thin wrappers around the POSIX terminal APIs, which work the same way on every
+// Unix-like platform once the controlling tty has been opened.
+#[cfg(all(unix, feature = "std"))]
+mod unix {
+    use std::ffi::OsString;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::mem;
+    use std::os::unix::ffi::OsStringExt;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::{AsRawFd, RawFd};
+    use std::path::{Path, PathBuf};
+
+    use ::{CttyError, Dev};
+
+    extern crate libc;
+
+    #[cfg(not(target_os = "redox"))]
+    use self::libc::getsid;
+
+    extern "C" {
+        // Provided by relibc, but missing from the libc crate's Redox bindings
+        #[cfg(target_os = "redox")]
+        fn getsid(pid: self::libc::pid_t) -> self::libc::pid_t;
+
+        // Missing from the libc crate. Solaris's plain getlogin_r predates POSIX's
+        #[cfg(not(any(target_os = "android", target_os = "redox")))]
+        #[cfg_attr(any(target_os = "illumos", target_os = "solaris"), link_name = "__posix_getlogin_r")]
+        fn getlogin_r(buf: *mut self::libc::c_char, bufsize: self::libc::size_t) -> self::libc::c_int;
+
+        // Only in the libc crate's bindings for a few platforms
+        #[cfg(not(target_os = "android"))]
+        fn ctermid(s: *mut self::libc::c_char) -> *mut self::libc::c_char;
+    }
+
+    /// Opens /dev/tty, which always refers to the current process's controlling tty
+    fn open_dev_tty() -> Result<File, CttyError> {
+        OpenOptions::new().read(true).write(true).open("/dev/tty").map_err(map_tty_error)
+    }
+
+    /// Opens the current process's controlling tty for reading and writing, by the
+    /// path of its device
+    ///
+    /// Unlike /dev/tty, the File refers to the device itself. It's opened with
+    /// O_NOCTTY, so this never makes the terminal the ctty of a process without one.
+    pub fn open_ctty() -> Result<File, CttyError> {
+        let path = ::get_path_for_dev_buf(::get_ctty_dev()?)?;
+        OpenOptions::new().read(true).write(true)
+            .custom_flags(self::libc::O_NOCTTY)
+            .open(path)
+            .map_err(map_tty_error)
+    }
+
+    /// Converts an error from a terminal operation into a CttyError
+    fn map_tty_error(e: io::Error) -> CttyError {
+        match e.raw_os_error() {
+            // /dev/tty fails with ENXIO when there's no controlling tty to open
+            Some(self::libc::ENXIO) | Some(self::libc::ENOTTY) => CttyError::NotFound,
+            Some(errno @ self::libc::EACCES) | Some(errno @ self::libc::EPERM) => {
+                CttyError::SystemPermissionFailure(errno)
+            },
+            _ => CttyError::IOError(e)
+        }
+    }
+
+    /// Returns the foreground process group of the current process's controlling tty
+    pub fn get_foreground_pgrp() -> Result<i32, CttyError> {
+        let tty = open_dev_tty()?;
+        let pgrp = unsafe { self::libc::tcgetpgrp(tty.as_raw_fd()) };
+        if pgrp < 0 {
+            return Err(map_tty_error(io::Error::last_os_error()));
+        }
+        Ok(pgrp)
+    }
+
+    /// Returns the session id of the current process's controlling tty
+    ///
+    /// This only needs /dev/tty, so unlike get_ctty_dev it keeps working when /proc
+    /// isn't mounted.
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "illumos",
+              target_os = "solaris"))]
+    pub fn get_session_id() -> Result<i32, CttyError> {
+        let tty = open_dev_tty()?;
+        let mut sid: self::libc::pid_t = 0;
+        if unsafe { self::libc::ioctl(tty.as_raw_fd(), self::libc::TIOCGSID, &mut sid) } < 0 {
+            return Err(map_tty_error(io::Error::last_os_error()));
+        }
+        Ok(sid)
+    }
+
+    /// Returns the size of the current process's controlling tty as (rows, columns)
+    pub fn get_winsize() -> Result<(u16, u16), CttyError> {
+        let tty = open_dev_tty()?;
+        let mut ws: self::libc::winsize = unsafe { mem::zeroed() };
+        if unsafe { self::libc::ioctl(tty.as_raw_fd(), self::libc::TIOCGWINSZ, &mut ws) } < 0 {
+            return Err(map_tty_error(io::Error::last_os_error()));
+        }
+        Ok((ws.ws_row, ws.ws_col))
+    }
+
+    /// Returns the path of the terminal open on fd
+    ///
+    /// Unlike get_ctty_path, this only works if fd hasn't been redirected, but it's a
+    /// useful fallback where the controlling tty can't be looked up directly.
+    pub fn ttyname_of_fd(fd: RawFd) -> Result<PathBuf, CttyError> {
+        // Grow the buffer until the name fits, rather than risk truncating it
+        let mut buf: Vec<u8> = vec![0; 64];
+        loop {
+            let res = unsafe {
+                self::libc::ttyname_r(fd, buf.as_mut_ptr() as *mut self::libc::c_char, buf.len())
+            };
+            match res {
+                0 => break,
+                self::libc::ERANGE if buf.len() < 4096 => {
+                    let len = buf.len() * 2;
+                    buf.resize(len, 0);
+                },
+                e => return Err(map_tty_error(io::Error::from_raw_os_error(e)))
+            }
+        }
+
+        let len = buf.iter().position(|&b| b == 0).ok_or(CttyError::SystemDataParseFailure)?;
+        buf.truncate(len);
+        Ok(PathBuf::from(OsString::from_vec(buf)))
+    }
+
+    /// Returns whether fd is open on the current process's controlling tty
+    ///
+    /// This is useful for checking that an inherited fd really is the ctty. An fd that
+    /// isn't a terminal yields false rather than an error.
+    pub fn matches_fd(fd: RawFd) -> Result<bool, CttyError> {
+        let mut st: self::libc::stat = unsafe { mem::zeroed() };
+        if unsafe { self::libc::fstat(fd, &mut st) } < 0 {
+            return Err(CttyError::IOError(io::Error::last_os_error()));
+        }
+        if st.st_mode & self::libc::S_IFMT != self::libc::S_IFCHR
+            || unsafe { self::libc::isatty(fd) } == 0 {
+            return Ok(false);
+        }
+
+        #[allow(clippy::unnecessary_cast)] // dev_t is narrower than 64 bits on some platforms
+        let dev = Dev(st.st_rdev as u64);
+        match ::get_ctty_dev() {
+            Ok(ctty) => Ok(dev == ctty),
+            Err(CttyError::NotFound) => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Returns the dev_t of the character device at path, e.g. to compare a terminal
+    /// path from an argument against `get_ctty_dev`
+    ///
+    /// This is the inverse of `get_path_for_dev`. Symlinks are followed. Fails with
+    /// `CttyError::NotFound` if nothing exists at path or it isn't a character device.
+    pub fn dev_from_path(path: &Path) -> Result<Dev, CttyError> {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        let meta = path.metadata().map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => CttyError::NotFound,
+            _ => CttyError::IOError(e)
+        })?;
+        if !meta.file_type().is_char_device() {
+            return Err(CttyError::NotFound);
+        }
+        Ok(Dev(meta.rdev()))
+    }
+
+    /// Returns the name of the user logged in on the current process's controlling tty
+    ///
+    /// This is looked up with getlogin_r, which usually consults utmp, so it fails with
+    /// NotFound in sessions that never logged in (e.g. many terminal emulators and
+    /// containers). Some systems report an empty name for such sessions instead.
+    #[cfg(not(any(target_os = "android", target_os = "redox")))]
+    pub fn get_login_name() -> Result<String, CttyError> {
+        let mut buf: Vec<u8> = vec![0; 64];
+        loop {
+            let res = unsafe { getlogin_r(buf.as_mut_ptr() as *mut self::libc::c_char, buf.len()) };
+            match res {
+                0 => break,
+                self::libc::ERANGE if buf.len() < 4096 => {
+                    let len = buf.len() * 2;
+                    buf.resize(len, 0);
+                },
+                // glibc reports a missing utmp entry as ENOENT, and musl reports ENXIO
+                self::libc::ENOENT | self::libc::ENXIO => return Err(CttyError::NotFound),
+                e => return Err(map_tty_error(io::Error::from_raw_os_error(e)))
+            }
+        }
+
+        let len = buf.iter().position(|&b| b == 0).ok_or(CttyError::SystemDataParseFailure)?;
+        buf.truncate(len);
+        String::from_utf8(buf).map_err(|_| CttyError::SystemDataParseFailure)
+    }
+
+    /// Returns the name of the user logged in on the current process's controlling tty
+    ///
+    /// This is looked up with getlogin, so it fails with NotFound in sessions that
+    /// never logged in. Some systems report an empty name for such sessions instead.
+    #[cfg(any(target_os = "android", target_os = "redox"))]
+    pub fn get_login_name() -> Result<String, CttyError> {
+        // getlogin_r only exists from Android 9 and has no Redox binding, so use the
+        // static buffer instead
+        let name = unsafe { self::libc::getlogin() };
+        if name.is_null() {
+            return Err(CttyError::NotFound);
+        }
+        let name = unsafe { std::ffi::CStr::from_ptr(name) };
+        name.to_str().map(String::from).map_err(|_| CttyError::SystemDataParseFailure)
+    }
+
+    /// Returns the path of the first of stdin, stdout, and stderr that is this
+    /// process's controlling tty
+    pub(crate) fn ttyname_of_session_fd() -> Result<PathBuf, CttyError> {
+        // tcgetsid only succeeds on the controlling tty, which belongs to our session
+        let sid = unsafe { getsid(0) };
+        for fd in 0..3 {
+            if unsafe { self::libc::tcgetsid(fd) } == sid {
+                return ttyname_of_fd(fd);
+            }
+        }
+        Err(CttyError::NotFound)
+    }
+
+    /// Returns the path that ctermid gives for the current process's controlling tty,
+    /// or None if it gives an empty one
+    ///
+    /// This is almost always /dev/tty, which stands for whichever terminal is the
+    /// ctty, rather than the path of the actual device. It's also returned whether or
+    /// not the process has a ctty at all. `ttyname_of_ctermid` resolves it to the
+    /// actual device.
+    #[cfg(not(target_os = "android"))]
+    pub fn ctermid_path() -> Option<PathBuf> {
+        use std::ffi::{CStr, OsStr};
+        use std::os::unix::ffi::OsStrExt;
+
+        // Comfortably larger than L_ctermid, which the libc crate doesn't expose
+        let mut buf = [0 as self::libc::c_char; 256];
+        let name = unsafe { CStr::from_ptr(ctermid(buf.as_mut_ptr())) };
+        match name.to_bytes() {
+            b"" => None,
+            name => Some(PathBuf::from(OsStr::from_bytes(name)))
+        }
+    }
+
+    /// Returns the path that ctermid gives for the current process's controlling tty,
+    /// or None if it gives an empty one
+    ///
+    /// Bionic's ctermid (which older API levels lack) always gives /dev/tty, so that's
+    /// returned directly. It stands for whichever terminal is the ctty, rather than
+    /// the path of the actual device, and is returned whether or not the process has
+    /// a ctty at all. `ttyname_of_ctermid` resolves it to the actual device.
+    #[cfg(target_os = "android")]
+    pub fn ctermid_path() -> Option<PathBuf> {
+        Some(PathBuf::from("/dev/tty"))
+    }
+
+    /// Opens the terminal named by ctermid (normally /dev/tty) and returns its ttyname
+    pub(crate) fn ttyname_of_ctermid() -> Result<PathBuf, CttyError> {
+        let name = ctermid_path().ok_or(CttyError::NotFound)?;
+
+        // Opening it fails when there's no ctty, which is how one is detected here
+        let tty = OpenOptions::new().read(true).write(true)
+            .custom_flags(self::libc::O_NOCTTY)
+            .open(name)
+            .map_err(|e| match e.raw_os_error() {
+                Some(self::libc::ENXIO) => CttyError::NotFound,
+                _ if e.kind() == io::ErrorKind::NotFound => CttyError::NotFound,
+                _ => CttyError::IOError(e)
+            })?;
+        ttyname_of_fd(tty.as_raw_fd())
+    }
+
+    /// Detaches the current process from its controlling tty by moving it into a new
+    /// session with setsid, e.g. when daemonizing
+    ///
+    /// Afterwards, has_ctty returns false, and the ctty cached by get_ctty_dev_cached
+    /// is forgotten. A process group leader can't call setsid, which is reported as
+    /// `CttyError::AlreadySessionLeader`; the usual fix is to fork first and detach
+    /// in the child.
+    pub fn detach_ctty() -> Result<(), CttyError> {
+        if unsafe { self::libc::setsid() } < 0 {
+            let e = io::Error::last_os_error();
+            return Err(match e.raw_os_error() {
+                Some(self::libc::EPERM) => CttyError::AlreadySessionLeader,
+                _ => CttyError::IOError(e)
+            });
+        }
+        ::clear_ctty_cache();
+        Ok(())
+    }
+
+    /// Gives up the current process's controlling tty with the TIOCNOTTY ioctl,
+    /// without leaving its session
+    ///
+    /// Unlike `detach_ctty`, this works for a process group leader, and the process
+    /// keeps its session and process group, so it can't acquire a new ctty afterwards
+    /// unless it leads the session. If it does lead the session, the whole session
+    /// loses the terminal, and its foreground process group is sent SIGHUP. Fails
+    /// with `CttyError::NotFound` if there's no ctty to drop.
+    #[cfg(not(any(target_os = "haiku", target_os = "redox")))]
+    pub fn drop_ctty() -> Result<(), CttyError> {
+        // Missing from the libc crate on these platforms
+        #[cfg(target_os = "android")]
+        const TIOCNOTTY: u32 = 0x5422;
+        #[cfg(any(target_os = "netbsd", target_os = "openbsd", target_os = "hurd"))]
+        const TIOCNOTTY: u32 = 0x2000_7471; // _IO('t', 113)
+        #[cfg(not(any(target_os = "android", target_os = "netbsd", target_os = "openbsd",
+                      target_os = "hurd")))]
+        use self::libc::TIOCNOTTY;
+
+        let tty = open_dev_tty()?;
+        // TIOCNOTTY is narrower than ioctl's request type on some platforms, hence the cast
+        if unsafe { self::libc::ioctl(tty.as_raw_fd(), TIOCNOTTY as _) } < 0 {
+            return Err(map_tty_error(io::Error::last_os_error()));
+        }
+        ::clear_ctty_cache();
+        Ok(())
+    }
+
+    /// Makes the terminal at path the current process's controlling tty, with the
+    /// TIOCSCTTY ioctl
+    ///
+    /// The process must be a session leader with no controlling tty, which usually
+    /// means calling setsid (or detach_ctty) first. Otherwise, or if the terminal is
+    /// already another session's ctty, this fails with
+    /// `CttyError::SystemPermissionFailure`.
+    #[cfg(not(target_os = "aix"))]
+    pub fn acquire_ctty(path: &Path) -> Result<(), CttyError> {
+        // Open with O_NOCTTY so that only the ioctl below decides whether it's adopted
+        let tty = OpenOptions::new().read(true).write(true)
+            .custom_flags(self::libc::O_NOCTTY).open(path)?;
+        // TIOCSCTTY is narrower than ioctl's request type on some BSDs, hence the cast
+        if unsafe { self::libc::ioctl(tty.as_raw_fd(), self::libc::TIOCSCTTY as _, 0) } < 0 {
+            let e = io::Error::last_os_error();
+            return Err(match e.raw_os_error() {
+                Some(errno @ self::libc::EPERM) | Some(errno @ self::libc::EINVAL) => {
+                    CttyError::SystemPermissionFailure(errno)
+                },
+                _ => map_tty_error(e)
+            });
+        }
+        ::clear_ctty_cache();
+        Ok(())
+    }
+
+    /// Makes the terminal at path the current process's controlling tty
+    ///
+    /// AIX has no TIOCSCTTY, but a session leader with no controlling tty adopts the
+    /// first terminal it opens without O_NOCTTY, so that's done instead. The same
+    /// requirements apply: otherwise, or if the terminal is already another session's
+    /// ctty, this fails with `CttyError::SystemPermissionFailure`.
+    #[cfg(target_os = "aix")]
+    pub fn acquire_ctty(path: &Path) -> Result<(), CttyError> {
+        let tty = OpenOptions::new().read(true).write(true).open(path)?;
+        // Opening succeeds whether or not the terminal was adopted, so check
+        if unsafe { self::libc::tcgetsid(tty.as_raw_fd()) != getsid(0) } {
+            return Err(CttyError::SystemPermissionFailure(self::libc::EPERM));
+        }
+        ::clear_ctty_cache();
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Runs f in a forked child, asserting that it returned true
+        ///
+        /// The child exits without unwinding or running the harness's cleanup, so f
+        /// can leave its process in whatever state it likes (e.g. without a ctty).
+        fn in_forked_child<F: FnOnce() -> bool>(f: F) {
+            match unsafe { self::libc::fork() } {
+                0 => {
+                    let ok = f();
+                    unsafe { self::libc::_exit(if ok { 0 } else { 1 }) };
+                },
+                child => {
+                    assert!(child > 0);
+                    let mut status = 0;
+                    assert_eq!(unsafe { self::libc::waitpid(child, &mut status, 0) }, child);
+                    assert!(self::libc::WIFEXITED(status));
+                    assert_eq!(self::libc::WEXITSTATUS(status), 0);
+                }
+            }
+        }
+
+        #[test]
+        fn test_get_foreground_pgrp() -> Result<(), CttyError> {
+            assert!(get_foreground_pgrp()? > 0);
+            Ok(())
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "illumos",
+                  target_os = "solaris"))]
+        #[test]
+        fn test_get_session_id() -> Result<(), CttyError> {
+            assert_eq!(get_session_id()?, unsafe { self::libc::getsid(0) });
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_winsize() -> Result<(), CttyError> {
+            let (rows, cols) = get_winsize()?;
+            assert!(rows > 0 && cols > 0);
+            Ok(())
+        }
+
+        #[test]
+        fn test_ttyname_of_fd() -> Result<(), Box<dyn std::error::Error>> {
+            let null = File::open("/dev/null")?;
+            assert!(matches!(ttyname_of_fd(null.as_raw_fd()), Err(CttyError::NotFound)));
+
+            let path = ::get_path_for_dev_buf(::get_ctty_dev()?)?;
+            let tty = File::open(&path)?;
+            assert_eq!(ttyname_of_fd(tty.as_raw_fd())?, path);
+            Ok(())
+        }
+
+        #[test]
+        fn test_get_login_name() {
+            // Only a login shell has a name to find, so anything else must be NotFound
+            match get_login_name() {
+                Ok(name) => assert!(!name.contains('\0')),
+                Err(e) => assert_eq!(e, CttyError::NotFound)
+            }
+        }
+
+        #[test]
+        fn test_open_ctty() -> Result<(), Box<dyn std::error::Error>> {
+            let tty = open_ctty()?;
+            assert_eq!(unsafe { self::libc::isatty(tty.as_raw_fd()) }, 1);
+            assert!(matches_fd(tty.as_raw_fd())?);
+            Ok(())
+        }
+
+        #[test]
+        fn test_ctermid_path() -> Result<(), Box<dyn std::error::Error>> {
+            let path = ctermid_path().expect("ctermid gave an empty path");
+            assert!(path.is_absolute());
+            // Opening what ctermid names finds the same terminal as the session fds,
+            // unless ttyname (as on Linux) just reports the path it was opened by
+            let name = ttyname_of_ctermid()?;
+            assert!(name == ttyname_of_session_fd()? || name == path);
+            Ok(())
+        }
+
+        #[test]
+        fn test_matches_fd() -> Result<(), Box<dyn std::error::Error>> {
+            let null = File::open("/dev/null")?;
+            assert!(!matches_fd(null.as_raw_fd())?);
+
+            let path = ::get_path_for_dev_buf(::get_ctty_dev()?)?;
+            let tty = File::open(&path)?;
+            assert!(matches_fd(tty.as_raw_fd())?);
+
+            // stdin only matches if it hasn't been redirected away from the ctty
+            let stdin_is_ctty = ttyname_of_fd(0).map(|p| p == path).unwrap_or(false);
+            assert_eq!(matches_fd(0)?, stdin_is_ctty);
+            Ok(())
+        }
+
+        #[test]
+        fn test_detach_ctty() {
+            in_forked_child(|| {
+                // The forked child isn't a group leader, so only the second detach
+                // should be refused
+                detach_ctty().is_ok() && !::has_ctty()
+                    && detach_ctty() == Err(CttyError::AlreadySessionLeader)
+            });
+        }
+
+        #[cfg(not(any(target_os = "haiku", target_os = "redox")))]
+        #[test]
+        fn test_drop_ctty() {
+            in_forked_child(|| {
+                // The forked child doesn't lead the session, so only it loses the
+                // terminal, and there's nothing left for a second drop
+                drop_ctty().is_ok() && !::has_ctty() && drop_ctty() == Err(CttyError::NotFound)
+            });
+            assert!(::has_ctty());
+        }
+
+        #[test]
+        fn test_watch_ctty() {
+            use std::thread;
+            use std::time::Duration;
+
+            in_forked_child(|| {
+                // Lose the ctty partway through the watch, and give up if the watch
+                // never notices
+                thread::spawn(|| {
+                    thread::sleep(Duration::from_millis(50));
+                    let _ = detach_ctty();
+                    thread::sleep(Duration::from_secs(5));
+                    unsafe { self::libc::_exit(2) };
+                });
+                let mut seen = Vec::new();
+                let res = ::watch_ctty(Duration::from_millis(5), |dev| {
+                    seen.push(dev);
+                    false
+                });
+                res.is_ok() && seen == vec![None]
+            });
+        }
+
+        #[test]
+        fn test_acquire_ctty() {
+            use std::ffi::CStr;
+            use std::os::unix::fs::MetadataExt;
+
+            let flags = self::libc::O_RDWR | self::libc::O_NOCTTY;
+            let master = unsafe { self::libc::posix_openpt(flags) };
+            // Nothing to check if this system can't allocate ptys
+            if master < 0 {
+                return;
+            }
+            let pts = unsafe {
+                assert_eq!(self::libc::grantpt(master), 0);
+                assert_eq!(self::libc::unlockpt(master), 0);
+                PathBuf::from(CStr::from_ptr(self::libc::ptsname(master)).to_str().unwrap())
+            };
+            let pts_dev = std::fs::metadata(&pts).unwrap().rdev() as u64;
+
+            in_forked_child(|| {
+                // Adopting a tty is refused until the child leads its own session
+                let refused = CttyError::SystemPermissionFailure(self::libc::EPERM);
+                acquire_ctty(&pts) == Err(refused)
+                    && detach_ctty().is_ok() && acquire_ctty(&pts).is_ok()
+                    && ::get_ctty_dev().ok() == Some(::Dev(pts_dev))
+            });
+            unsafe { self::libc::close(master) };
+        }
+
+        #[test]
+        fn test_map_tty_error() {
+            let no_tty = map_tty_error(io::Error::from_raw_os_error(self::libc::ENOTTY));
+            assert!(matches!(no_tty, CttyError::NotFound));
+
+            let denied = map_tty_error(io::Error::from_raw_os_error(self::libc::EACCES));
+            assert!(matches!(denied, CttyError::SystemPermissionFailure(self::libc::EACCES)));
+        }
+    }
+}
+#[cfg(all(unix, feature = "std"))]
+pub use unix::*;
+
+// Login records, read with the POSIX utmpx API. musl only provides it as stubs, and
+// Android and OpenBSD don't provide it at all
+#[cfg(all(feature = "utmp",
+          any(all(target_os = "linux", target_env = "gnu"), target_os = "macos",
+              target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd",
+              target_os = "illumos", target_os = "solaris")))]
+mod utmp {
+    use std::mem;
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+
+    use ::CttyError;
+
+    extern crate libc;
+
+    /// A login record from the utmp database
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct UtmpEntry {
+        /// The name of the logged in user
+        pub user: String,
+        /// The remote host the user logged in from, or empty for a local login
+        pub host: String,
+        /// When the user logged in
+        pub login_time: SystemTime
+    }
+
+    /// Returns the utmp record of the login on the current process's controlling tty
+    ///
+    /// This is the entry `who` would print for the ctty, found by its name relative to
+    /// /dev (e.g. pts/3). Only logins recorded in utmp can be found, which excludes
+    /// many terminal emulators and containers, so those fail with NotFound.
+    pub fn get_utmp_entry() -> Result<UtmpEntry, CttyError> {
+        // The utmpx functions share a single cursor into the database
+        static UTMP_LOCK: Mutex<()> = Mutex::new(());
+
+        let name = ::get_ctty_name()?;
+        let mut query: self::libc::utmpx = unsafe { mem::zeroed() };
+        if name.len() >= query.ut_line.len() {
+            return Err(CttyError::NotFound);
+        }
+        for (dst, &src) in query.ut_line.iter_mut().zip(name.as_bytes()) {
+            *dst = src as self::libc::c_char;
+        }
+
+        let _guard = UTMP_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = unsafe {
+            self::libc::setutxent();
+            let found = self::libc::getutxline(&query);
+            let entry = if found.is_null() { None } else { Some(*found) };
+            self::libc::endutxent();
+            entry
+        };
+        let entry = entry.ok_or(CttyError::NotFound)?;
+        trace!(line = %name, "found utmp entry");
+
+        #[cfg(any(target_os = "dragonfly", target_os = "netbsd"))]
+        let user = &entry.ut_name;
+        #[cfg(not(any(target_os = "dragonfly", target_os = "netbsd")))]
+        let user = &entry.ut_user;
+
+        // Records from before 1970 are bogus, so call them the epoch
+        let secs = entry.ut_tv.tv_sec.max(0) as u64;
+        let micros = entry.ut_tv.tv_usec.max(0) as u32;
+        Ok(UtmpEntry {
+            user: utmp_field(user)?,
+            host: utmp_field(&entry.ut_host)?,
+            login_time: UNIX_EPOCH + Duration::new(secs, micros.min(999_999) * 1000)
+        })
+    }
+
+    /// Converts a fixed-size utmp field, which is only NUL-terminated if it's short
+    /// enough, into a String
+    fn utmp_field(field: &[self::libc::c_char]) -> Result<String, CttyError> {
+        let bytes = field.iter().take_while(|&&c| c != 0).map(|&c| c as u8).collect();
+        String::from_utf8(bytes).map_err(|_| CttyError::SystemDataParseFailure)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_get_utmp_entry() -> Result<(), CttyError> {
+            // Best effort, since only terminals someone logged in on have an entry
+            let entry = match get_utmp_entry() {
+                Err(CttyError::NotFound) => return Ok(()),
+                res => res?
+            };
+            assert!(!entry.user.is_empty());
+            assert!(entry.login_time <= SystemTime::now());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(feature = "utmp",
+          any(all(target_os = "linux", target_env = "gnu"), target_os = "macos",
+              target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd",
+              target_os = "illumos", target_os = "solaris")))]
+pub use utmp::*;
+
+// Fallback for every other platform, including Windows (which has consoles rather
+// than ttys). These exist so that portable code can depend on ctty-rs unconditionally.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+              target_os = "dragonfly", target_os = "macos", target_os = "netbsd",
+              target_os = "openbsd", target_os = "illumos", target_os = "solaris",
+              target_os = "aix", target_os = "haiku", target_os = "hurd",
+              target_os = "redox")))]
+mod unsupported {
+    #[cfg(feature = "std")]
+    use std::path::{Path, PathBuf};
+
+    use ::{CttyError, Dev};
+
+    /// Always returns `CttyError::Unsupported`
+    pub fn get_ctty_dev() -> Result<Dev, CttyError> {
+        Err(CttyError::Unsupported)
+    }
+
+    /// Always returns `CttyError::Unsupported`
+    pub fn get_ctty_dev_for_pid(_pid: u32) -> Result<Dev, CttyError> {
+        Err(CttyError::Unsupported)
+    }
+
+    /// Always returns `CttyError::Unsupported`
+    pub fn get_parent_ctty_dev() -> Result<Dev, CttyError> {
+        Err(CttyError::Unsupported)
+    }
+
+    /// Always returns false, since ttys aren't supported on this platform
+    pub fn is_pty(_dev: Dev) -> bool {
+        false
+    }
+
+    /// Always returns `CttyError::Unsupported`
+    #[cfg(feature = "std")]
+    pub fn get_path_for_dev_buf(_dev: Dev) -> Result<PathBuf, CttyError> {
+        Err(CttyError::Unsupported)
+    }
+
+    /// Always returns `CttyError::Unsupported`
+    #[cfg(feature = "std")]
+    pub fn get_path_for_dev_in(_dev: Dev, _dev_root: &Path) -> Result<PathBuf, CttyError> {
+        Err(CttyError::Unsupported)
+    }
+
+    /// Always returns `CttyError::Unsupported`
+    #[cfg(feature = "std")]
+    pub fn get_paths_for_dev(_dev: Dev) -> Result<Vec<PathBuf>, CttyError> {
+        Err(CttyError::Unsupported)
+    }
+
+    /// Always returns `CttyError::Unsupported`
+    #[cfg(feature = "std")]
+    pub fn get_name_for_dev(_dev: Dev) -> Result<String, CttyError> {
+        Err(CttyError::Unsupported)
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_unsupported() {
+            assert_eq!(get_ctty_dev(), Err(CttyError::Unsupported));
+            assert_eq!(get_path_for_dev_buf(Dev(0)), Err(CttyError::Unsupported));
+            assert!(!::has_ctty());
+        }
+    }
+}
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+              target_os = "dragonfly", target_os = "macos", target_os = "netbsd",
+              target_os = "openbsd", target_os = "illumos", target_os = "solaris",
+              target_os = "aix", target_os = "haiku", target_os = "hurd",
+              target_os = "redox")))]
+pub use unsupported::*;
+
+/// A way of finding the device node for a dev_t, for device trees that the
+/// platform's own lookup can't find (or mocks of them)
+#[cfg(feature = "std")]
+pub trait DeviceScanner {
+    /// Returns the path of a device node for dev, or None if there isn't one
+    fn find(&self, dev: Dev) -> Result<Option<PathBuf>, CttyError>;
+}
+
+/// The DeviceScanner used by `get_path_for_dev`, which looks under /dev the same
+/// way as `get_path_for_dev_buf`
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsDeviceScanner;
+
+#[cfg(feature = "std")]
+impl DeviceScanner for FsDeviceScanner {
+    fn find(&self, dev: Dev) -> Result<Option<PathBuf>, CttyError> {
+        match get_path_for_dev_buf(dev) {
+            Ok(path) => Ok(Some(path)),
+            Err(CttyError::NotFound) => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+}
+
+/// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t,
+/// as found by scanner
+#[cfg(feature = "std")]
+pub fn get_path_for_dev_with(dev: Dev, scanner: &dyn DeviceScanner) -> Result<PathBuf, CttyError> {
+    scanner.find(dev)?.ok_or(CttyError::NotFound)
+}
+
+/// Returns a full path to a tty or pseudo tty that corresponds with the given dev_t
+///
+/// Paths that aren't valid UTF-8 are converted lossily; use `get_path_for_dev_buf`
+/// to get the path exactly as the system reports it.
+#[cfg(feature = "std")]
+pub fn get_path_for_dev(dev: Dev) -> Result<String, CttyError> {
+    let path = get_path_for_dev_with(dev, &FsDeviceScanner)?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Returns a full path to the current process's controlling tty
+///
+/// Like `get_path_for_dev`, a path that isn't valid UTF-8 is converted lossily, so it
+/// may not name the device anymore. Use `get_ctty_path_os` if the path is going to be
+/// opened or compared.
+#[cfg(feature = "std")]
+pub fn get_ctty_path() -> Result<String, CttyError> {
+    let dev = get_ctty_dev()?;
+    get_path_for_dev(dev)
+}
+
+/// Returns a full path to the current process's controlling tty, exactly as the
+/// system reports it
+///
+/// Device paths are arbitrary bytes on Linux, and while devname's names on the BSDs
+/// are ASCII in practice, they go through an OsString here too for consistency.
+#[cfg(feature = "std")]
+pub fn get_ctty_path_os() -> Result<OsString, CttyError> {
+    let dev = get_ctty_dev()?;
+    Ok(get_path_for_dev_buf(dev)?.into_os_string())
+}
+
+/// A named way of finding the controlling tty's path, see get_ctty_path_with_fallbacks
+#[cfg(all(unix, feature = "std"))]
+type PathStrategy = (&'static str, fn() -> Result<PathBuf, CttyError>);
+
+#[cfg(all(unix, feature = "std"))]
+const PATH_STRATEGIES: &[PathStrategy] = &[
+    ("get_ctty_dev", || get_path_for_dev_buf(get_ctty_dev()?)),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    ("/dev/tty", || get_path_for_dev_buf(linux::get_ctty_dev_from_dev_tty()?)),
+    ("ttyname", unix::ttyname_of_session_fd),
+    ("ctermid", unix::ttyname_of_ctermid)
+];
+
+/// Returns a full path to the current process's controlling tty, trying several
+/// lookup strategies in turn
+///
+/// The strategies are, in order:
+///
+/// * `get_ctty_dev` (e.g. /proc/self/stat on Linux), resolved with `get_path_for_dev_buf`
+/// * On Linux and Android, asking /dev/tty for its underlying device
+/// * `ttyname_of_fd` on whichever of stdin, stdout, and stderr is the controlling tty
+/// * `ttyname_of_fd` on the terminal named by `ctermid_path`. Some systems (including
+///   Linux) report the path it was opened by, so this can give /dev/tty rather than
+///   the actual device
+///
+/// The first success is returned, and `CttyError::NotFound` only if every strategy
+/// fails. With the `tracing` feature, the strategy that succeeded is logged.
+#[cfg(all(unix, feature = "std"))]
+pub fn get_ctty_path_with_fallbacks() -> Result<PathBuf, CttyError> {
+    first_success(PATH_STRATEGIES)
+}
+
+/// Returns the result of the first strategy that succeeds
+#[cfg(all(unix, feature = "std"))]
+fn first_success(strategies: &[PathStrategy]) -> Result<PathBuf, CttyError> {
+    for &(_name, strategy) in strategies {
+        if let Ok(path) = strategy() {
+            debug!(strategy = _name, path = %path.display(), "found controlling tty");
+            return Ok(path);
+        }
+    }
+    Err(CttyError::NotFound)
+}
+
+/// Returns the name of the current process's controlling tty relative to /dev/,
+/// e.g. pts/3
+#[cfg(feature = "std")]
+pub fn get_ctty_name() -> Result<String, CttyError> {
+    let dev = get_ctty_dev()?;
+    get_name_for_dev(dev)
+}
+
+/// Returns the kind of terminal the current process's controlling tty is
+///
+/// On Linux and Android this goes by the device's major and minor numbers, and
+/// elsewhere by its name under /dev.
+#[cfg(any(feature = "std", target_os = "linux", target_os = "android"))]
+pub fn classify_ctty() -> Result<CttyKind, CttyError> {
+    Ok(classify_dev(get_ctty_dev()?))
+}
+
+/// Returns the kind of terminal the given dev_t is, going by its name under /dev
+///
+/// Device numbers are allocated dynamically on most of these platforms, so the name
+/// is all there is to go on. A device without one is `CttyKind::Other`.
+#[cfg(all(feature = "std", not(any(target_os = "linux", target_os = "android"))))]
+pub fn classify_dev(dev: Dev) -> CttyKind {
+    let name = match get_name_for_dev(dev) {
+        Ok(name) => name,
+        Err(_) => return CttyKind::Other
+    };
+    if is_pty(dev) {
+        return CttyKind::Pseudo(trailing_number(&name).unwrap_or(0));
+    }
+    classify_name(&name)
+}
+
+/// Classifies a terminal that isn't a pty by its name relative to /dev
+#[cfg(all(feature = "std", not(any(target_os = "linux", target_os = "android"))))]
+fn classify_name(name: &str) -> CttyKind {
+    let tail = |prefix: &str| name.strip_prefix(prefix).filter(|t| !t.is_empty());
+
+    // syscons and vt on FreeBSD and DragonFly BSD, and wscons on NetBSD and OpenBSD
+    if let Some(n) = tail("ttyv").or_else(|| tail("ttyC")).or_else(|| tail("ttyE")) {
+        if let Ok(n) = u32::from_str_radix(n, 16) {
+            return CttyKind::VirtualConsole(n);
+        }
+    }
+    // The Hurd numbers its consoles tty1 and up, where others use ttyN for serial ports
+    if let Some(n) = tail("tty").and_then(|n| n.parse().ok()) {
+        return if cfg!(target_os = "hurd") {
+            CttyKind::VirtualConsole(n)
+        } else {
+            CttyKind::SerialConsole
+        };
+    }
+
+    let serial = ["ttyu", "ttyU", "tty0", "cua", "dty", "term/", "ports/", "com"];
+    if serial.iter().any(|prefix| tail(prefix).is_some()) {
+        return CttyKind::SerialConsole;
+    }
+    CttyKind::Other
+}
+
+/// Returns the pty number at the end of a name, e.g. 3 for pts/3 or ttys003. Legacy
+/// BSD pty names end in a single letter or digit instead, e.g. ttypa for pty 10
+#[cfg(all(feature = "std", not(any(target_os = "linux", target_os = "android"))))]
+fn trailing_number(name: &str) -> Option<u32> {
+    let digits = name.len() - name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 {
+        return name[name.len() - digits..].parse().ok();
+    }
+    name.chars().last().and_then(|c| c.to_digit(36))
+}
+
+/// Returns whether the current process has a controlling tty
+///
+/// Any error looking up the controlling tty, including IO and permission errors,
+/// is treated as not having one.
+pub fn has_ctty() -> bool {
+    get_ctty_dev().is_ok()
+}
+
+/// Returns the dev_t corresponding to the current process's controlling tty, or None
+/// if it has none (as is normal for daemons)
+///
+/// Unlike `has_ctty`, IO and permission errors are still returned as errors.
+pub fn try_get_ctty_dev() -> Result<Option<Dev>, CttyError> {
+    match get_ctty_dev() {
+        Err(CttyError::NotFound) => Ok(None),
+        Ok(dev) => Ok(Some(dev)),
+        Err(e) => Err(e)
+    }
+}
+
+/// Returns whether the processes with the given pids have the same controlling tty,
+/// as when grouping processes by terminal
+///
+/// Two processes without a ctty aren't sharing one, so that's false, as is a pid
+/// with no process behind it.
+pub fn same_ctty(pid_a: u32, pid_b: u32) -> Result<bool, CttyError> {
+    let ctty_of = |pid| match get_ctty_dev_for_pid(pid) {
+        Err(CttyError::NotFound) => Ok(None),
+        Ok(dev) => Ok(Some(dev)),
+        Err(e) => Err(e)
+    };
+    match (ctty_of(pid_a)?, ctty_of(pid_b)?) {
+        (Some(a), Some(b)) => Ok(a == b),
+        _ => Ok(false)
+    }
+}
+
+/// Returns the dev_t corresponding to the controlling tty of the session with the
+/// given id, as reported for its session leader (whose pid is the sid)
+///
+/// Every process in a session shares the leader's ctty. Fails with
+/// `CttyError::NotFound` if the leader is gone.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn get_ctty_dev_for_session(sid: i32) -> Result<Dev, CttyError> {
+    if sid <= 0 {
+        return Err(CttyError::NotFound);
+    }
+    get_ctty_dev_for_pid(sid as u32)
+}
+
+/// Cached result of get_ctty_dev, see get_ctty_dev_cached. A OnceLock can't be
+/// reset through a shared reference, so this uses a Mutex to allow clearing it
+#[cfg(feature = "std")]
+static CTTY_DEV_CACHE: Mutex<Option<Dev>> = Mutex::new(None);
+
+/// Returns the dev_t corresponding to the current process's controlling tty,
+/// only asking the system the first time it succeeds
+///
+/// This assumes the controlling tty doesn't change for the life of the process.
+/// Errors aren't cached, so a failed lookup is retried on the next call. Call
+/// `clear_ctty_cache` after anything that changes the ctty, such as `setsid`.
+#[cfg(feature = "std")]
+pub fn get_ctty_dev_cached() -> Result<Dev, CttyError> {
+    // A poisoned lock can only hold a fully written value, so it's safe to keep using
+    let mut cache = CTTY_DEV_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(dev) = *cache {
+        return Ok(dev);
+    }
+
+    let dev = get_ctty_dev()?;
+    *cache = Some(dev);
+    Ok(dev)
+}
+
+/// Forgets the dev_t cached by `get_ctty_dev_cached`
+#[cfg(feature = "std")]
+pub fn clear_ctty_cache() {
+    *CTTY_DEV_CACHE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Blocks, calling cb with the current process's controlling tty each time it
+/// changes, for as long as cb returns true
+///
+/// There's no notification when a process gains or loses its ctty (e.g. after
+/// `setsid`), so this polls get_ctty_dev every interval. Having no ctty is reported
+/// as None. Only changes are reported, not the ctty at the time of the call. Any
+/// error other than NotFound ends the watch and is returned.
+#[cfg(feature = "std")]
+pub fn watch_ctty<F>(interval: Duration, mut cb: F) -> Result<(), CttyError>
+    where F: FnMut(Option<Dev>) -> bool {
+    let mut last = try_get_ctty_dev()?;
+    loop {
+        thread::sleep(interval);
+        let dev = try_get_ctty_dev()?;
+        if dev != last {
+            debug!(?last, ?dev, "controlling tty changed");
+            last = dev;
+            if !cb(dev) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// A controlling tty, identified by both its dev_t and its path
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Ctty {
+    pub dev: Dev,
+    pub path: PathBuf
+}
+
+#[cfg(feature = "std")]
+impl Ctty {
+    /// Looks up the current process's controlling tty
+    pub fn for_current_process() -> Result<Ctty, CttyError> {
+        let dev = get_ctty_dev()?;
+        let path = get_path_for_dev_buf(dev)?;
+        Ok(Ctty { dev, path })
+    }
+}
+
+/// Displays as the path followed by the device number, e.g. /dev/pts/3 (136:3)
+#[cfg(feature = "std")]
+impl fmt::Display for Ctty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.path.display(), self.dev)
+    }
+}
+
+/// Result of the first Ctty::for_current_process call made through ctty
+#[cfg(feature = "std")]
+static CTTY: OnceLock<Result<Ctty, CttyError>> = OnceLock::new();
+
+/// Returns the current process's controlling tty, looking it up only on the first call
+///
+/// Unlike `get_ctty_dev_cached`, the first result is kept for the life of the process
+/// whether or not it succeeded, and can't be cleared. Every call returns a reference
+/// to that same result, so this is only suitable for programs whose ctty doesn't
+/// change after startup.
+#[cfg(feature = "std")]
+pub fn ctty() -> Result<&'static Ctty, &'static CttyError> {
+    CTTY.get_or_init(Ctty::for_current_process).as_ref()
+}
+
+/// A terminal device, with its path, device numbers, and whether it's a pseudo tty
+#[cfg(all(feature = "std",
+          any(target_os = "linux", target_os = "android", target_os = "freebsd",
+              target_os = "dragonfly", target_os = "macos", target_os = "netbsd",
+              target_os = "openbsd", target_os = "illumos", target_os = "solaris",
+              target_os = "aix")))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TerminalDevice {
+    pub path: PathBuf,
+    pub dev: Dev,
+    pub major: u32,
+    pub minor: u32,
+    pub is_pty: bool
+}
+
+/// Looks up everything about the current process's controlling tty at once
+///
+/// /dev is only scanned once, to find the path. Only platforms that split device
+/// numbers into a major and minor are supported.
+#[cfg(all(feature = "std",
+          any(target_os = "linux", target_os = "android", target_os = "freebsd",
+              target_os = "dragonfly", target_os = "macos", target_os = "netbsd",
+              target_os = "openbsd", target_os = "illumos", target_os = "solaris",
+              target_os = "aix")))]
+pub fn resolve_ctty() -> Result<TerminalDevice, CttyError> {
+    let dev = get_ctty_dev()?;
+    let path = get_path_for_dev_buf(dev)?;
+
+    // is_pty would scan /dev again here, so go by the path that was just found
+    #[cfg(any(target_os = "illumos", target_os = "solaris", target_os = "aix"))]
+    let is_pty = path.starts_with("/dev/pts");
+    #[cfg(not(any(target_os = "illumos", target_os = "solaris", target_os = "aix")))]
+    let is_pty = is_pty(dev);
+
+    Ok(TerminalDevice { path, dev, major: major(dev), minor: minor(dev), is_pty })
+}
+
+/// Serialized form of CttyError, since std::io::Error can't be serialized directly
+#[cfg(all(feature = "std", feature = "serde"))]
+#[derive(Serialize, Deserialize)]
+enum CttyErrorRepr {
+    NotFound,
+    DeviceNotInDev,
+    DevptsNotMounted,
+    SystemDataParseFailure,
+    SystemPermissionFailure(i32),
+    Unsupported,
+    AlreadySessionLeader,
+    IOError { kind: String, message: String }
+}
+
+#[cfg(all(feature = "std", feature = "serde"))]
+impl Serialize for CttyError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = match *self {
+            CttyError::NotFound => CttyErrorRepr::NotFound,
+            CttyError::DeviceNotInDev => CttyErrorRepr::DeviceNotInDev,
+            CttyError::DevptsNotMounted => CttyErrorRepr::DevptsNotMounted,
+            CttyError::SystemDataParseFailure => CttyErrorRepr::SystemDataParseFailure,
+            CttyError::SystemPermissionFailure(errno) => CttyErrorRepr::SystemPermissionFailure(errno),
+            CttyError::Unsupported => CttyErrorRepr::Unsupported,
+            CttyError::AlreadySessionLeader => CttyErrorRepr::AlreadySessionLeader,
+            CttyError::IOError(ref e) => CttyErrorRepr::IOError {
+                kind: format!("{:?}", e.kind()),
+                message: e.to_string()
+            }
+        };
+        repr.serialize(serializer)
+    }
+}
+
+#[cfg(all(feature = "std", feature = "serde"))]
+impl<'de> Deserialize<'de> for CttyError {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use std::io::{Error, ErrorKind};
+
+        Ok(match CttyErrorRepr::deserialize(deserializer)? {
+            CttyErrorRepr::NotFound => CttyError::NotFound,
+            CttyErrorRepr::DeviceNotInDev => CttyError::DeviceNotInDev,
+            CttyErrorRepr::DevptsNotMounted => CttyError::DevptsNotMounted,
+            CttyErrorRepr::SystemDataParseFailure => CttyError::SystemDataParseFailure,
+            CttyErrorRepr::SystemPermissionFailure(errno) => CttyError::SystemPermissionFailure(errno),
+            CttyErrorRepr::Unsupported => CttyError::Unsupported,
+            CttyErrorRepr::AlreadySessionLeader => CttyError::AlreadySessionLeader,
+            CttyErrorRepr::IOError { kind, message } => {
+                // Only the common kinds are recognized, anything else becomes Other
+                let kind = match kind.as_str() {
+                    "NotFound" => ErrorKind::NotFound,
+                    "PermissionDenied" => ErrorKind::PermissionDenied,
+                    "AlreadyExists" => ErrorKind::AlreadyExists,
+                    "WouldBlock" => ErrorKind::WouldBlock,
+                    "InvalidInput" => ErrorKind::InvalidInput,
+                    "InvalidData" => ErrorKind::InvalidData,
+                    "TimedOut" => ErrorKind::TimedOut,
+                    "Interrupted" => ErrorKind::Interrupted,
+                    "UnexpectedEof" => ErrorKind::UnexpectedEof,
+                    _ => ErrorKind::Other
+                };
+                CttyError::IOError(Error::new(kind, message))
+            }
+        })
+    }
+}
+
+/// Re-exports of the most commonly used items, for `use ctty::prelude::*`
+pub mod prelude {
+    pub use ::{get_ctty_dev, CttyError, CttyKind};
+    #[cfg(feature = "std")]
+    pub use ::{get_ctty_path, get_path_for_dev, Ctty};
+}
+
+#[cfg(all(test, unix, feature = "std"))]
+mod tests {
+    use std::error::Error;
+    use ::get_path_for_dev;
+    use ::get_path_for_dev_buf;
+    use ::get_ctty_dev;
+    use ::get_ctty_dev_for_pid;
+    use ::get_ctty_name;
+    use ::{get_ctty_path, get_ctty_path_os};
+    use ::has_ctty;
+    use ::is_pty;
+    use ::{clear_ctty_cache, get_ctty_dev_cached};
+    use ::{ctty, Ctty};
+    use ::Dev;
+
+    #[test]
+    fn test_get_ctty_dev() -> Result<(), Box<dyn Error>> {
+        let dev = get_ctty_dev().unwrap();
+        dbg!(dev);
+        let path = get_path_for_dev(dev)?;
+        dbg!(path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ctty_path() -> Result<(), Box<dyn Error>> {
+        let path = get_ctty_path()?;
+        assert!(path.starts_with("/dev/"));
+        assert_eq!(get_ctty_path_os()?, *path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ctty_dev_for_pid() -> Result<(), Box<dyn Error>> {
+        assert_eq!(get_ctty_dev_for_pid(std::process::id())?, get_ctty_dev()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ctty_name() -> Result<(), Box<dyn Error>> {
+        let name = get_ctty_name()?;
+        assert!(!name.starts_with('/'));
+        assert_eq!(format!("/dev/{}", name), get_ctty_path()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dev_from_path() -> Result<(), Box<dyn Error>> {
+        use std::path::Path;
+        use ::{dev_from_path, CttyError};
+
+        let dev = get_ctty_dev()?;
+        assert_eq!(dev_from_path(Path::new(&get_path_for_dev(dev)?))?, dev);
+
+        assert!(matches!(dev_from_path(Path::new("/")), Err(CttyError::NotFound)));
+        assert!(matches!(dev_from_path(Path::new("/nonexistent/tty")), Err(CttyError::NotFound)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ctty_path_with_fallbacks() -> Result<(), Box<dyn Error>> {
+        use ::{get_ctty_path_with_fallbacks, PATH_STRATEGIES};
+
+        let path = get_ctty_path_with_fallbacks()?;
+        assert_eq!(path, get_path_for_dev_buf(get_ctty_dev()?)?);
+
+        // Every fallback should find the same tty on its own, except that ctermid's
+        // may only know it as /dev/tty
+        for &(name, strategy) in &PATH_STRATEGIES[1..] {
+            let found = strategy()?;
+            assert!(found == path || (name == "ctermid" && Some(found) == ::ctermid_path()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_first_success_falls_back() {
+        use std::path::PathBuf;
+        use ::{first_success, CttyError, PathStrategy};
+
+        let broken: PathStrategy = ("broken", || Err(CttyError::SystemPermissionFailure(1)));
+        let working: PathStrategy = ("working", || Ok(PathBuf::from("/dev/pts/7")));
+
+        assert_eq!(first_success(&[broken, working]).unwrap(), PathBuf::from("/dev/pts/7"));
+        assert!(matches!(first_success(&[broken, broken]), Err(CttyError::NotFound)));
+    }
+
+    #[test]
+    fn test_get_parent_ctty_dev() -> Result<(), Box<dyn Error>> {
+        // The test harness's parent (cargo) shares its terminal
+        assert_eq!(::get_parent_ctty_dev()?, get_ctty_dev()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_has_ctty() {
+        assert!(has_ctty());
+    }
+
+    #[test]
+    fn test_get_path_for_dev_with() -> Result<(), Box<dyn Error>> {
+        use std::collections::HashMap;
+        use std::path::PathBuf;
+        use ::{get_path_for_dev_with, CttyError, DeviceScanner, FsDeviceScanner};
+
+        struct MockScanner(HashMap<Dev, PathBuf>);
+        impl DeviceScanner for MockScanner {
+            fn find(&self, dev: Dev) -> Result<Option<PathBuf>, CttyError> {
+                Ok(self.0.get(&dev).cloned())
+            }
+        }
+
+        let mut devices = HashMap::new();
+        devices.insert(Dev(7), PathBuf::from("/net/host/dev/ttyS0"));
+        let mock = MockScanner(devices);
+        assert_eq!(get_path_for_dev_with(Dev(7), &mock)?, PathBuf::from("/net/host/dev/ttyS0"));
+        assert!(matches!(get_path_for_dev_with(Dev(8), &mock), Err(CttyError::NotFound)));
+
+        let dev = get_ctty_dev()?;
+        assert_eq!(get_path_for_dev_with(dev, &FsDeviceScanner)?, get_path_for_dev_buf(dev)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_ctty() -> Result<(), Box<dyn Error>> {
+        use ::same_ctty;
+
+        let pid = std::process::id();
+        assert!(same_ctty(pid, pid)?);
+        assert!(!same_ctty(pid, u32::MAX)?);
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "illumos",
+              target_os = "solaris"))]
+    #[test]
+    fn test_get_ctty_dev_for_session() -> Result<(), Box<dyn Error>> {
+        use ::{get_ctty_dev_for_session, get_session_id, CttyError};
+
+        assert_eq!(get_ctty_dev_for_session(get_session_id()?)?, get_ctty_dev()?);
+        assert!(matches!(get_ctty_dev_for_session(i32::MAX), Err(CttyError::NotFound)));
+        assert!(matches!(get_ctty_dev_for_session(0), Err(CttyError::NotFound)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_get_ctty_dev() -> Result<(), Box<dyn Error>> {
+        assert_eq!(::try_get_ctty_dev()?, Some(get_ctty_dev()?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ctty_dev_cached() -> Result<(), Box<dyn Error>> {
+        let dev = get_ctty_dev()?;
+        assert_eq!(get_ctty_dev_cached()?, dev);
+        assert_eq!(get_ctty_dev_cached()?, dev);
+        clear_ctty_cache();
+        assert_eq!(get_ctty_dev_cached()?, dev);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_pty_matches_path() -> Result<(), Box<dyn Error>> {
+        let dev = get_ctty_dev()?;
+        let path = get_path_for_dev(dev)?;
+        if path.starts_with("/dev/pts/") {
+            assert!(is_pty(dev));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_ctty() -> Result<(), Box<dyn Error>> {
+        use ::CttyKind;
+
+        let kind = ::classify_ctty()?;
+        assert_eq!(is_pty(get_ctty_dev()?), matches!(kind, CttyKind::Pseudo(_)));
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    #[test]
+    fn test_classify_name() {
+        use ::{classify_name, trailing_number, CttyKind};
+
+        assert_eq!(classify_name("ttyv1"), CttyKind::VirtualConsole(1));
+        assert_eq!(classify_name("ttyCa"), CttyKind::VirtualConsole(10));
+        assert_eq!(classify_name("ttyu0"), CttyKind::SerialConsole);
+        assert_eq!(classify_name("term/a"), CttyKind::SerialConsole);
+        assert_eq!(classify_name("console"), CttyKind::Other);
+        assert_eq!(trailing_number("pts/12"), Some(12));
+        assert_eq!(trailing_number("ttys003"), Some(3));
+        assert_eq!(trailing_number("ttypa"), Some(10));
+    }
+
+    #[test]
+    fn test_ctty_for_current_process() -> Result<(), Box<dyn Error>> {
+        let ctty = Ctty::for_current_process()?;
+        assert_eq!(ctty.dev, get_ctty_dev()?);
+        assert_eq!(ctty.path, get_path_for_dev_buf(ctty.dev)?);
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+              target_os = "dragonfly", target_os = "macos", target_os = "netbsd",
+              target_os = "openbsd", target_os = "illumos", target_os = "solaris",
+              target_os = "aix"))]
+    #[test]
+    fn test_resolve_ctty() -> Result<(), Box<dyn Error>> {
+        extern crate libc;
+        use ::resolve_ctty;
+
+        let tty = resolve_ctty()?;
+        assert_eq!(tty.dev, get_ctty_dev()?);
+        assert_eq!(tty.path, get_path_for_dev_buf(tty.dev)?);
+        // dev_t is narrower than 64 bits on some platforms, and makedev is only a safe
+        // const fn on some
+        #[allow(clippy::unnecessary_cast, unused_unsafe)]
+        let rebuilt = Dev(unsafe { libc::makedev(tty.major as _, tty.minor as _) } as u64);
+        assert_eq!(rebuilt, tty.dev);
+        assert_eq!(tty.is_pty, is_pty(tty.dev));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctty_display() -> Result<(), Box<dyn Error>> {
+        let ctty = Ctty::for_current_process()?;
+        let shown = ctty.to_string();
+        assert!(shown.starts_with(&*ctty.path.to_string_lossy()));
+        assert!(shown.ends_with(&format!("({})", ctty.dev)));
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            use std::path::PathBuf;
+            let pts = Ctty { dev: Dev(34819), path: PathBuf::from("/dev/pts/3") };
+            assert_eq!(pts.to_string(), "/dev/pts/3 (136:3)");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctty_cached_once() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Ctty>();
+        assert_send_sync::<::CttyError>();
+
+        match (ctty(), ctty()) {
+            (Ok(a), Ok(b)) => assert!(std::ptr::eq(a, b)),
+            (Err(a), Err(b)) => assert!(std::ptr::eq(a, b)),
+            _ => panic!("ctty returned different results")
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() -> Result<(), Box<dyn Error>> {
+        extern crate serde_json;
+        use std::io;
+        use std::path::PathBuf;
+        use ::CttyError;
+
+        let ctty = Ctty { dev: Dev(34816), path: PathBuf::from("/dev/pts/0") };
+        let json = serde_json::to_string(&ctty)?;
+        assert_eq!(json, r#"{"dev":34816,"path":"/dev/pts/0"}"#);
+        assert_eq!(serde_json::from_str::<Ctty>(&json)?, ctty);
+
+        let err = serde_json::to_string(&CttyError::NotFound)?;
+        assert!(matches!(serde_json::from_str(&err)?, CttyError::NotFound));
+
+        let io_err = CttyError::IOError(io::Error::new(io::ErrorKind::InvalidData, "bad stat"));
+        match serde_json::from_str(&serde_json::to_string(&io_err)?)? {
+            CttyError::IOError(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                assert_eq!(e.to_string(), "bad stat");
+            },
+            e => panic!("unexpected error {:?}", e)
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dev() -> Result<(), Box<dyn Error>> {
+        let dev = get_ctty_dev()?;
+        assert_eq!(Dev::from(u64::from(dev)), dev);
+
+        let mut devs = vec![Dev(2), Dev(0), Dev(1)];
+        devs.sort();
+        assert_eq!(devs, vec![Dev(0), Dev(1), Dev(2)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_eq() {
+        use std::io;
+        use ::CttyError;
+
+        assert_eq!(get_ctty_dev_for_pid(u32::MAX), Err(CttyError::NotFound));
+        assert_eq!(CttyError::SystemPermissionFailure(13), CttyError::SystemPermissionFailure(13));
+        assert_ne!(CttyError::SystemPermissionFailure(13), CttyError::SystemPermissionFailure(1));
+        assert_ne!(CttyError::NotFound, CttyError::SystemDataParseFailure);
+
+        let timed_out = || CttyError::IOError(io::Error::from(io::ErrorKind::TimedOut));
+        assert_eq!(timed_out(), CttyError::IOError(io::Error::new(io::ErrorKind::TimedOut, "slow")));
+        assert_ne!(timed_out(), CttyError::IOError(io::Error::from(io::ErrorKind::InvalidData)));
+    }
+
+    #[test]
+    fn test_permission_failure_message() {
+        let err = ::CttyError::SystemPermissionFailure(13);
+        assert!(err.to_string().ends_with("(os error 13)"));
+    }
+
+    // Runs both with and without the thiserror feature, which must format identically
+    #[test]
+    fn test_error_display() {
+        use std::io;
+        use ::CttyError;
+
+        let cases = [
+            (CttyError::NotFound, "Controlling TTY for this process not found".to_string()),
+            (CttyError::DeviceNotInDev,
+             "Controlling TTY exists but has no device node in the searched /dev".to_string()),
+            (CttyError::DevptsNotMounted,
+             "Pseudo tty requested, but devpts is not mounted at the searched /dev/pts".to_string()),
+            (CttyError::SystemDataParseFailure,
+             "System returned invalid data when looking up CTTY".to_string()),
+            (CttyError::SystemPermissionFailure(13),
+             format!("Failed to request CTTY information from system: {}",
+                     io::Error::from_raw_os_error(13))),
+            (CttyError::Unsupported, "Controlling TTYs are not supported on this platform".to_string()),
+            (CttyError::AlreadySessionLeader,
+             "Process is already a session leader, so it can't detach from its CTTY".to_string()),
+            (CttyError::IOError(io::Error::other("bad stat")), "bad stat".to_string())
+        ];
+        for (err, msg) in cases.iter() {
+            assert_eq!(err.to_string(), *msg);
+        }
+
+        let err: CttyError = io::Error::from(io::ErrorKind::TimedOut).into();
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn test_error_clone() {
+        use std::io;
+        use ::CttyError;
+
+        assert_eq!(CttyError::NotFound.clone(), CttyError::NotFound);
+
+        let errno = CttyError::IOError(io::Error::from_raw_os_error(5));
+        let cloned = errno.clone();
+        assert!(matches!(cloned, CttyError::IOError(ref e) if e.raw_os_error() == Some(5)));
+        assert_eq!(cloned.to_string(), errno.to_string());
+
+        let custom = CttyError::IOError(io::Error::new(io::ErrorKind::InvalidData, "bad stat"));
+        let cloned = custom.clone();
+        assert_eq!(cloned, custom);
+        assert_eq!(cloned.to_string(), "bad stat");
+    }
+
+    #[test]
+    fn test_error_kind() {
+        use std::io;
+        use ::{CttyError, CttyErrorKind};
+
+        let kinds = [
+            (CttyError::NotFound, CttyErrorKind::NotFound),
+            (CttyError::DeviceNotInDev, CttyErrorKind::NotFound),
+            (CttyError::DevptsNotMounted, CttyErrorKind::NotFound),
+            (CttyError::SystemDataParseFailure, CttyErrorKind::ParseFailure),
+            (CttyError::SystemPermissionFailure(1), CttyErrorKind::PermissionFailure),
+            (CttyError::Unsupported, CttyErrorKind::Unsupported),
+            (CttyError::AlreadySessionLeader, CttyErrorKind::PermissionFailure),
+            (CttyError::IOError(io::Error::other("bad stat")), CttyErrorKind::Io)
+        ];
+        for (err, kind) in kinds.iter() {
+            assert_eq!(err.kind(), *kind);
+        }
+    }
+
+    #[test]
+    fn test_into_io_error() {
+        use std::io;
+        use ::CttyError;
+
+        let kinds = [
+            (CttyError::NotFound, io::ErrorKind::NotFound),
+            (CttyError::DeviceNotInDev, io::ErrorKind::NotFound),
+            (CttyError::DevptsNotMounted, io::ErrorKind::NotFound),
+            (CttyError::SystemDataParseFailure, io::ErrorKind::InvalidData),
+            (CttyError::SystemPermissionFailure(13), io::ErrorKind::PermissionDenied),
+            (CttyError::IOError(io::Error::from(io::ErrorKind::TimedOut)), io::ErrorKind::TimedOut)
+        ];
+        for (err, kind) in kinds {
+            let message = err.to_string();
+            let io_err = io::Error::from(err);
+            assert_eq!(io_err.kind(), kind);
+            assert_eq!(io_err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_get_path_for_dev_buf() -> Result<(), Box<dyn Error>> {
+        let dev = get_ctty_dev()?;
+        let path = get_path_for_dev_buf(dev)?;
+        assert_eq!(path.to_string_lossy(), get_path_for_dev(dev)?);
         Ok(())
     }
 }